@@ -0,0 +1,76 @@
+// The JSON grammar shared by the benches, so that they all measure the same parser
+
+use std::{collections::HashMap, str};
+use parze::{prelude::*, memo::Memo};
+
+type Error = DefaultError<u8>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Str(String),
+    Num(f64),
+    Array(Vec<JsonValue>),
+    Object(HashMap<String,JsonValue>)
+}
+
+/// What is done to the parser of each value, such as memoizing it.
+pub trait Wrap: Clone + 'static {
+    fn wrap<P>(self, value: Parser<P, Error>) -> Parser<impl Pattern<Error, Input=u8, Output=JsonValue>, Error>
+        where P: Pattern<Error, Input=u8, Output=JsonValue>;
+}
+
+/// Leaves values unchanged.
+impl Wrap for () {
+    fn wrap<P>(self, value: Parser<P, Error>) -> Parser<impl Pattern<Error, Input=u8, Output=JsonValue>, Error>
+        where P: Pattern<Error, Input=u8, Output=JsonValue>
+    {
+        value
+    }
+}
+
+impl Wrap for Memo {
+    fn wrap<P>(self, value: Parser<P, Error>) -> Parser<impl Pattern<Error, Input=u8, Output=JsonValue>, Error>
+        where P: Pattern<Error, Input=u8, Output=JsonValue>
+    {
+        value.memoized(&self)
+    }
+}
+
+pub fn json<W: Wrap>(wrap: W) -> Parser<impl Pattern<Error, Input=u8, Output=JsonValue>, Error> {
+    parsers! {
+        integer = { { one_of(b"123456789") }.% % { one_of(b"0123456789") }* | b'0'.% }
+        frac = { b'.'.% % { one_of(b"0123456789") }+ }
+        exp = { (b'e' | b'E').% % (b'+' | b'-')? % { one_of(b"0123456789") }+ }
+        number: f64 = { b'-'? % integer % frac?.# % exp?.# => { |b| str::from_utf8(&b).unwrap().parse().unwrap() } }
+
+        special = { b'\\' | b'/' | b'"' | b'b' -> b'\x08' | b'f' -> b'\x0C' | b'n' -> b'\n' | b'r' -> b'\r' | b't' -> b'\t' }
+        escape = { b'\\' -& special }
+        string: String = { b'"' -& ({ none_of(b"\\\"") } | escape)* &- b'"' => { |b| String::from_utf8(b).unwrap() } }
+
+        elements = { value ... b','~ }
+        array = { b'['~ -& elements &- b']' }
+
+        member = { string~ &- b':'~ & value }
+        members = { member ... b','~ }
+
+        object: HashMap<String, JsonValue> = { b'{'~ -& members &- b'}' => { |m| m.into_iter().collect() } }
+
+        unwrapped: JsonValue = {
+            ~(
+                | b"null" => { |_| JsonValue::Null }
+                | b"true" => { |_| JsonValue::Bool(true) }
+                | b"false" => { |_| JsonValue::Bool(false) }
+                | number => { |n| JsonValue::Num(n) }
+                | string => { |s| JsonValue::Str(s) }
+                | array => { |a| JsonValue::Array(a) }
+                | object => { |o| JsonValue::Object(o) }
+            )~
+        }
+
+        value: JsonValue = { { wrap.clone().wrap(unwrapped.link()) } }
+    }
+
+    value
+}
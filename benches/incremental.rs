@@ -0,0 +1,37 @@
+#![cfg(feature = "macros")]
+#![feature(test)]
+
+extern crate test;
+
+mod grammar;
+
+use test::{Bencher, black_box};
+use parze::memo::Memo;
+
+// Parses the sample once, then reparses it after changing one character each iteration
+fn reparse(b: &mut Bencher, memo: &Memo, incremental: bool) {
+    let json = grammar::json(memo.clone());
+    let mut src = include_bytes!("sample.json").to_vec();
+    let pos = src.windows(12).position(|w| w == b"\"index\": 33,").unwrap() + 10;
+    json.parse(src.iter().copied()).unwrap();
+
+    b.iter(|| {
+        src[pos] = if src[pos] == b'3' { b'4' } else { b'3' };
+        if incremental {
+            memo.edit(pos..pos + 1, 1);
+        } else {
+            memo.clear();
+        }
+        black_box(json.parse(src.iter().copied()).unwrap())
+    });
+}
+
+#[bench]
+fn full(b: &mut Bencher) {
+    reparse(b, &Memo::new(), false);
+}
+
+#[bench]
+fn incremental(b: &mut Bencher) {
+    reparse(b, &Memo::new(), true);
+}
@@ -1,28 +1,17 @@
 #![cfg(feature = "macros")]
-#![feature(proc_macro_hygiene)]
-
 #![feature(test)]
 
 extern crate test;
 
-use std::collections::HashMap;
-use test::{Bencher, black_box};
+mod grammar;
 
-#[derive(Debug, PartialEq)]
-pub enum JsonValue {
-    Null,
-    Bool(bool),
-    Str(String),
-    Num(f64),
-    Array(Vec<JsonValue>),
-    Object(HashMap<String,JsonValue>)
-}
+use test::{Bencher, black_box};
+use grammar::JsonValue;
 
 #[bench]
 fn parze(b: &mut Bencher) {
-    let json = parze::json();
-    b.iter(|| black_box(json.parse(include_bytes!("sample.json")).unwrap()));
-    //dbg!(parze::json().parse(include_bytes!("small_sample.json")));
+    let json = grammar::json(());
+    b.iter(|| black_box(json.parse(include_bytes!("sample.json").iter().copied()).unwrap()));
 }
 
 #[bench]
@@ -31,48 +20,6 @@ fn pom(b: &mut Bencher) {
     b.iter(|| black_box(json.parse(include_bytes!("sample.json")).unwrap()));
 }
 
-mod parze {
-    use parze::prelude::*;
-
-    use std::str;
-    use super::JsonValue;
-
-    pub fn json() -> Parser<'static, u8, JsonValue> {
-        parsers! {
-            integer = { { one_of(b"123456789") }.% % { one_of(b"0123456789") }* |% b'0'.% }
-            frac = { b'.'.% % { one_of(b"0123456789") }+ }
-            exp = { (b'e' | b'E').% % (b'+' | b'-')? % { one_of(b"0123456789") }+ }
-            number: Parser<_, _, _, _> = { b'-'? % integer % frac?.# % exp?.# => { |b| str::from_utf8(&b.as_slice()).unwrap().parse().unwrap() } }
-
-            special = { b'\\' | b'/' | b'"' | b'b' -> b'\x08' | b'f' -> b'\x0C' | b'n' -> b'\n' | b'r' -> b'\r' | b't' -> b'\t' }
-            escape = { b'\\' -& special }
-            string = { b'"' -& ({ none_of(b"\\\"") } | escape)* &- b'"' => { |b| String::from_utf8(b).unwrap() } }
-
-            elements = { value ... b','~ }
-            array = { b'['~ -& elements &- b']' }
-
-            member = { string~ &- b':'~ & value }
-            members = { member ... b','~ }
-
-            object = { b'{'~ -& members &- b'}' => { |m| m.collect() } }
-
-            value = {
-                ~(
-                    | { all_of(b"null") } => { |_| JsonValue::Null }
-                    | { all_of(b"true") } => { |_| JsonValue::Bool(true) }
-                    | { all_of(b"false") } => { |_| JsonValue::Bool(false) }
-                    | number => { |n| JsonValue::Num(n) }
-                    | string => { |s| JsonValue::Str(s) }
-                    | array => { |a| JsonValue::Array(a) }
-                    | object => { |o| JsonValue::Object(o) }
-                )~
-            }
-        }
-
-        value
-    }
-}
-
 mod pom {
     use pom::parser::*;
     use pom::Parser;
@@ -0,0 +1,226 @@
+//! The `#[derive(Parse)]` macro for parze. See `parze::parse` for documentation.
+
+extern crate proc_macro;
+
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote, ToTokens};
+use syn::{
+    parse_macro_input,
+    spanned::Spanned,
+    Attribute,
+    Data,
+    DeriveInput,
+    Fields,
+    GenericArgument,
+    LitStr,
+    PathArguments,
+    Type,
+};
+
+#[proc_macro_derive(Parse, attributes(parze))]
+pub fn derive_parse(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match derive(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+// The `#[parze(...)]` options attached to a struct, variant or field
+#[derive(Default)]
+struct Attrs {
+    just: Option<LitStr>,
+    keyword: Option<LitStr>,
+    after: Option<LitStr>,
+    sep: Option<LitStr>,
+}
+
+impl Attrs {
+    fn from(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut this = Self::default();
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("parze")) {
+            attr.parse_nested_meta(|meta| {
+                let slot = if meta.path.is_ident("just") {
+                    &mut this.just
+                } else if meta.path.is_ident("keyword") {
+                    &mut this.keyword
+                } else if meta.path.is_ident("after") {
+                    &mut this.after
+                } else if meta.path.is_ident("sep") {
+                    &mut this.sep
+                } else {
+                    return Err(meta.error("expected `just`, `keyword`, `after` or `sep`"));
+                };
+                *slot = Some(meta.value()?.parse()?);
+                Ok(())
+            })?;
+        }
+        Ok(this)
+    }
+
+    fn prefix(&self) -> Option<TokenStream> {
+        match (&self.just, &self.keyword) {
+            (Some(just), _) => Some(quote!(::parze::parse::token::<E>(#just))),
+            (_, Some(keyword)) => Some(quote!(::parze::parse::keyword::<E>(#keyword))),
+            (None, None) => None,
+        }
+    }
+
+    fn suffix(&self) -> Option<TokenStream> {
+        self.after.as_ref().map(|after| quote!(::parze::parse::token::<E>(#after)))
+    }
+}
+
+// Builds up a sequence of parsers, keeping only the outputs of fields
+struct Sequence {
+    parser: Option<TokenStream>,
+    pattern: Option<TokenStream>,
+}
+
+impl Sequence {
+    fn token(&mut self, token: TokenStream) {
+        self.parser = Some(match self.parser.take() {
+            None => token,
+            Some(parser) if self.pattern.is_some() => quote!(#parser.padded_by(#token)),
+            Some(parser) => quote!(#parser.padding_for(#token)),
+        });
+    }
+
+    fn field(&mut self, field: TokenStream, binding: &syn::Ident) {
+        self.parser = Some(match self.parser.take() {
+            None => field,
+            Some(parser) if self.pattern.is_some() => quote!(#parser.then(#field)),
+            Some(parser) => quote!(#parser.padding_for(#field)),
+        });
+        self.pattern = Some(match self.pattern.take() {
+            None => quote!(#binding),
+            Some(pattern) => quote!((#pattern, #binding)),
+        });
+    }
+}
+
+// If `ty` is `Wrapper<T>`, returns `T`
+fn unwrap<'a>(ty: &'a Type, wrapper: &str) -> Option<&'a Type> {
+    let path = match ty {
+        Type::Path(path) if path.qself.is_none() => &path.path,
+        _ => return None,
+    };
+    let last = path.segments.last()?;
+    if last.ident != wrapper {
+        return None;
+    }
+    match &last.arguments {
+        PathArguments::AngleBracketed(args) if args.args.len() == 1 => match &args.args[0] {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Surrounds `parser` with the tokens named by `attrs`
+fn surround(parser: TokenStream, attrs: &Attrs) -> TokenStream {
+    let parser = match attrs.prefix() {
+        Some(prefix) => quote!(#prefix.padding_for(#parser)),
+        None => parser,
+    };
+    match attrs.suffix() {
+        Some(suffix) => quote!(#parser.padded_by(#suffix)),
+        None => parser,
+    }
+}
+
+// The parser for a field, including its tokens. The tokens of an `Option` field are only expected if it is present.
+fn field_parser(ty: &Type, attrs: &Attrs) -> TokenStream {
+    if let Some(inner) = unwrap(ty, "Vec") {
+        let inner = field_parser(inner, &Attrs::default());
+        surround(match &attrs.sep {
+            Some(sep) => quote!(#inner.separated_by(::parze::parse::token::<E>(#sep))),
+            None => quote!(#inner.repeated()),
+        }, attrs)
+    } else if let Some(inner) = unwrap(ty, "Option") {
+        let inner = field_parser(inner, attrs);
+        quote!(#inner.or_not())
+    } else if let Some(inner) = unwrap(ty, "Box") {
+        let inner = field_parser(inner, &Attrs::default());
+        surround(quote!(#inner.map(::std::boxed::Box::new)), attrs)
+    } else {
+        surround(quote!(::parze::parse::lazy::<#ty, E>()), attrs)
+    }
+}
+
+// The parser for one struct or variant, constructed with `path`
+fn fields_parser(path: TokenStream, attrs: &Attrs, fields: &Fields, span: Span) -> syn::Result<TokenStream> {
+    if let Some(sep) = &attrs.sep {
+        return Err(syn::Error::new(sep.span(), "`sep` can only be used on `Vec` fields"));
+    }
+
+    let mut seq = Sequence { parser: None, pattern: None };
+    let mut bindings = Vec::new();
+
+    if let Some(prefix) = attrs.prefix() {
+        seq.token(prefix);
+    }
+    for (i, field) in fields.iter().enumerate() {
+        let field_attrs = Attrs::from(&field.attrs)?;
+        if field_attrs.sep.is_some() && unwrap(&field.ty, "Vec").is_none() {
+            return Err(syn::Error::new(field.ty.span(), "`sep` can only be used on `Vec` fields"));
+        }
+        let binding = format_ident!("__field{}", i);
+        seq.field(field_parser(&field.ty, &field_attrs), &binding);
+        bindings.push(binding);
+    }
+    if let Some(suffix) = attrs.suffix() {
+        seq.token(suffix);
+    }
+
+    let parser = seq.parser.ok_or_else(|| syn::Error::new(span, "a variant without fields needs a `just` or `keyword` attribute"))?;
+    let pattern = seq.pattern.unwrap_or_else(|| quote!(_));
+    let construct = match fields {
+        Fields::Named(named) => {
+            let names = named.named.iter().map(|field| &field.ident);
+            quote!(#path { #(#names: #bindings),* })
+        },
+        Fields::Unnamed(_) => quote!(#path(#(#bindings),*)),
+        Fields::Unit => path,
+    };
+    Ok(quote!(#parser.map(|#pattern| #construct)))
+}
+
+fn derive(input: &DeriveInput) -> syn::Result<TokenStream> {
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new(input.generics.span(), "`Parse` cannot be derived for generic types"));
+    }
+
+    let name = &input.ident;
+    let attrs = Attrs::from(&input.attrs)?;
+    let body = match &input.data {
+        Data::Struct(data) => fields_parser(quote!(#name), &attrs, &data.fields, name.span())?,
+        Data::Enum(data) => {
+            if attrs.prefix().is_some() || attrs.suffix().is_some() || attrs.sep.is_some() {
+                return Err(syn::Error::new(name.span(), "attributes on an enum must go on its variants"));
+            }
+            let mut variants = data.variants.iter().map(|variant| {
+                let ident = &variant.ident;
+                fields_parser(quote!(#name::#ident), &Attrs::from(&variant.attrs)?, &variant.fields, ident.span())
+            });
+            let first = variants
+                .next()
+                .ok_or_else(|| syn::Error::new(name.span(), "`Parse` cannot be derived for empty enums"))??;
+            variants.try_fold(first, |acc, variant| {
+                let variant = variant?;
+                Ok::<_, syn::Error>(quote!(#acc.or(#variant)))
+            })?
+        },
+        Data::Union(data) => return Err(syn::Error::new(data.union_token.span(), "`Parse` cannot be derived for unions")),
+    };
+
+    let name = name.to_token_stream();
+    Ok(quote! {
+        impl ::parze::parse::Parse for #name {
+            fn parser<E: ::parze::error::Error<char> + 'static>() -> ::parze::Parser<impl ::parze::Pattern<E, Input=char, Output=Self> + 'static, E> {
+                #body
+            }
+        }
+    })
+}
@@ -0,0 +1,332 @@
+//! Bidirectional parsers, which can also print an output back into the inputs it was parsed from.
+//!
+//! The combinators here mirror the usual ones, but build parsers that implement `Print` as well as `Pattern`, so a
+//! single definition gives both a parser and a printer that agree with it. Outputs are printed in a canonical form:
+//! `skip` parses anything its parser accepts, such as whitespace, but always prints the same inputs.
+//!
+//! ```ignore
+//! let entry = bidi::then(key, bidi::padding_for(bidi::just('='), value));
+//! let config = bidi::separated_by(entry, bidi::just('\n'));
+//!
+//! let entries = config.parse(text.chars())?;
+//! let text = config.print(&entries).unwrap().into_iter().collect::<String>();
+//! ```
+//!
+//! Bidirectional parsers may be used with ordinary combinators, but the results can only parse.
+
+use std::{
+    cell::RefCell,
+    marker::PhantomData,
+    rc::Rc,
+};
+use crate::{
+    primitives,
+    Parser,
+    Pattern,
+    Error,
+    Stream,
+    ParseResult,
+    grammar::{Grammar, Node},
+};
+
+/// A pattern that can print its outputs as the inputs it would parse them from.
+pub trait Print<E>: Pattern<E> {
+    /// Appends the inputs that `value` would be parsed from to `out`, returning `false` if it cannot be printed.
+    fn print(&self, value: &Self::Output, out: &mut Vec<Self::Input>) -> bool;
+}
+
+impl<P, E> Parser<P, E> {
+    /// Prints `value` as the inputs this parser would parse it from, if possible.
+    pub fn print(&self, value: &P::Output) -> Option<Vec<P::Input>>
+        where P: Print<E>
+    {
+        let mut out = Vec::new();
+        if self.pat.print(value, &mut out) { Some(out) } else { None }
+    }
+
+    fn print_into(&self, value: &P::Output, out: &mut Vec<P::Input>) -> bool
+        where P: Print<E>
+    {
+        self.pat.print(value, out)
+    }
+}
+
+// Parses with `parser`, and prints with `print`
+fn bidi<Q, F, E>(parser: Parser<Q, E>, print: F) -> Parser<impl Print<E, Input=Q::Input, Output=Q::Output>, E>
+    where
+        Q: Pattern<E>,
+        F: Fn(&Q::Output, &mut Vec<Q::Input>) -> bool + Clone,
+        E: Error<Q::Input>,
+{
+    struct Bidi<Q, F, E>(Q, F, PhantomData<E>);
+
+    impl<Q, F, E> Pattern<E> for Bidi<Q, F, E>
+        where
+            Q: Pattern<E>,
+            F: Fn(&Q::Output, &mut Vec<Q::Input>) -> bool + Clone,
+            E: Error<Q::Input>,
+    {
+        type Input = Q::Input;
+        type Output = Q::Output;
+
+        fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+            self.0.parse(stream)
+        }
+
+        fn cloned(&self) -> Self where Self: Sized {
+            Self(self.0.cloned(), self.1.clone(), PhantomData)
+        }
+
+        fn describe(&self, grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+            self.0.describe(grammar)
+        }
+    }
+
+    impl<Q, F, E> Print<E> for Bidi<Q, F, E>
+        where
+            Q: Pattern<E>,
+            F: Fn(&Q::Output, &mut Vec<Q::Input>) -> bool + Clone,
+            E: Error<Q::Input>,
+    {
+        fn print(&self, value: &Self::Output, out: &mut Vec<Self::Input>) -> bool {
+            (self.1)(value, out)
+        }
+    }
+
+    Parser::from_pat(Bidi(parser.pat, print, PhantomData))
+}
+
+// Just
+
+/// Parses exactly `item`.
+pub fn just<I, E>(item: I) -> Parser<impl Print<E, Input=I, Output=()>, E>
+    where
+        I: PartialEq + Clone,
+        E: Error<I>,
+{
+    let printed = item.clone();
+    bidi(primitives::just(item).to(()), move |_, out| { out.push(printed.clone()); true })
+}
+
+// Seq
+
+/// Parses exactly `items`, in order.
+pub fn seq<I, E>(items: impl IntoIterator<Item=I>) -> Parser<impl Print<E, Input=I, Output=()>, E>
+    where
+        I: PartialEq + Clone,
+        E: Error<I>,
+{
+    let items = items.into_iter().collect::<Vec<_>>();
+    let printed = items.clone();
+    bidi(primitives::seq(items).to(()), move |_, out| { out.extend(printed.iter().cloned()); true })
+}
+
+// Any
+
+pub fn any<I, E>() -> Parser<impl Print<E, Input=I, Output=I>, E>
+    where
+        I: Clone,
+        E: Error<I>,
+{
+    bidi(primitives::any(), |item: &I, out| { out.push(item.clone()); true })
+}
+
+// Filter
+
+/// Parses a symbol for which `f` holds. Only such symbols can be printed.
+pub fn filter<I, E>(label: &'static str, f: impl Fn(&I) -> bool + Clone) -> Parser<impl Print<E, Input=I, Output=I>, E>
+    where
+        I: Clone,
+        E: Error<I>,
+{
+    let check = f.clone();
+    bidi(primitives::filter(label, f), move |item: &I, out| check(item) && { out.push(item.clone()); true })
+}
+
+// Skip
+
+/// Parses with `parser`, discarding its output, and prints as `canonical`.
+///
+/// Useful for parts of the input that do not affect the output, such as whitespace.
+pub fn skip<P, E>(parser: Parser<P, E>, canonical: impl IntoIterator<Item=P::Input>) -> Parser<impl Print<E, Input=P::Input, Output=()>, E>
+    where
+        P: Pattern<E>,
+        P::Input: Clone,
+        E: Error<P::Input>,
+{
+    let canonical = canonical.into_iter().collect::<Vec<_>>();
+    bidi(parser.to(()), move |_, out| { out.extend(canonical.iter().cloned()); true })
+}
+
+// To
+
+/// Produces `value` for whatever `a` parses. Only `value` can be printed.
+pub fn to<A, U, E>(a: Parser<A, E>, value: U) -> Parser<impl Print<E, Input=A::Input, Output=U>, E>
+    where
+        A: Print<E, Output=()>,
+        U: PartialEq + Clone,
+        E: Error<A::Input>,
+{
+    let (pa, expected) = (a.clone(), value.clone());
+    bidi(a.to(value), move |value, out| *value == expected && pa.print_into(&(), out))
+}
+
+// Iso
+
+/// Converts the output of `a` with `f`. `g` is the inverse of `f`, giving `None` for values `f` cannot produce.
+pub fn iso<A, U, E>(
+    a: Parser<A, E>,
+    f: impl Fn(A::Output) -> U + Clone,
+    g: impl Fn(&U) -> Option<A::Output> + Clone,
+) -> Parser<impl Print<E, Input=A::Input, Output=U>, E>
+    where
+        A: Print<E>,
+        E: Error<A::Input>,
+{
+    let pa = a.clone();
+    bidi(a.map(f), move |value, out| g(value).is_some_and(|value| pa.print_into(&value, out)))
+}
+
+// Then
+
+#[allow(clippy::type_complexity)]
+pub fn then<A, B, E>(a: Parser<A, E>, b: Parser<B, E>) -> Parser<impl Print<E, Input=A::Input, Output=(A::Output, B::Output)>, E>
+    where
+        A: Print<E>,
+        B: Print<E, Input=A::Input>,
+        E: Error<A::Input>,
+{
+    let (pa, pb) = (a.clone(), b.clone());
+    bidi(a.then(b), move |(x, y), out| pa.print_into(x, out) && pb.print_into(y, out))
+}
+
+/// Parses `a` then `b`, keeping only the output of `b`.
+pub fn padding_for<A, B, E>(a: Parser<A, E>, b: Parser<B, E>) -> Parser<impl Print<E, Input=A::Input, Output=B::Output>, E>
+    where
+        A: Print<E, Output=()>,
+        B: Print<E, Input=A::Input>,
+        E: Error<A::Input>,
+{
+    let (pa, pb) = (a.clone(), b.clone());
+    bidi(a.padding_for(b), move |y, out| pa.print_into(&(), out) && pb.print_into(y, out))
+}
+
+/// Parses `a` then `b`, keeping only the output of `a`.
+pub fn padded_by<A, B, E>(a: Parser<A, E>, b: Parser<B, E>) -> Parser<impl Print<E, Input=A::Input, Output=A::Output>, E>
+    where
+        A: Print<E>,
+        B: Print<E, Input=A::Input, Output=()>,
+        E: Error<A::Input>,
+{
+    let (pa, pb) = (a.clone(), b.clone());
+    bidi(a.padded_by(b), move |x, out| pa.print_into(x, out) && pb.print_into(&(), out))
+}
+
+// Or
+
+/// Parses `a`, or `b` if that fails. Values are printed with `a` if possible, and otherwise with `b`.
+pub fn or<A, B, E>(a: Parser<A, E>, b: Parser<B, E>) -> Parser<impl Print<E, Input=A::Input, Output=A::Output>, E>
+    where
+        A: Print<E>,
+        B: Print<E, Input=A::Input, Output=A::Output>,
+        E: Error<A::Input>,
+{
+    let (pa, pb) = (a.clone(), b.clone());
+    bidi(a.or(b), move |x, out| {
+        let len = out.len();
+        pa.print_into(x, out) || { out.truncate(len); pb.print_into(x, out) }
+    })
+}
+
+// Repeated
+
+pub fn repeated<A, E>(a: Parser<A, E>) -> Parser<impl Print<E, Input=A::Input, Output=Vec<A::Output>>, E>
+    where
+        A: Print<E>,
+        E: Error<A::Input>,
+{
+    let pa = a.clone();
+    bidi(a.repeated(), move |xs: &Vec<_>, out| xs.iter().all(|x| pa.print_into(x, out)))
+}
+
+/// Like `repeated`, but requires at least one item. Empty lists cannot be printed.
+pub fn once_or_more<A, E>(a: Parser<A, E>) -> Parser<impl Print<E, Input=A::Input, Output=Vec<A::Output>>, E>
+    where
+        A: Print<E>,
+        E: Error<A::Input>,
+{
+    let pa = a.clone();
+    bidi(a.once_or_more(), move |xs: &Vec<_>, out| !xs.is_empty() && xs.iter().all(|x| pa.print_into(x, out)))
+}
+
+pub fn separated_by<A, B, E>(a: Parser<A, E>, sep: Parser<B, E>) -> Parser<impl Print<E, Input=A::Input, Output=Vec<A::Output>>, E>
+    where
+        A: Print<E>,
+        B: Print<E, Input=A::Input, Output=()>,
+        E: Error<A::Input>,
+{
+    let (pa, psep) = (a.clone(), sep.clone());
+    bidi(a.separated_by(sep), move |xs: &Vec<_>, out| xs.iter().enumerate().all(|(i, x)| {
+        (i == 0 || psep.print_into(&(), out)) && pa.print_into(x, out)
+    }))
+}
+
+// OrNot
+
+pub fn or_not<A, E>(a: Parser<A, E>) -> Parser<impl Print<E, Input=A::Input, Output=Option<A::Output>>, E>
+    where
+        A: Print<E>,
+        E: Error<A::Input>,
+{
+    let pa = a.clone();
+    bidi(a.or_not(), move |x: &Option<_>, out| x.as_ref().is_none_or(|x| pa.print_into(x, out)))
+}
+
+// Recursive
+
+type Slot<E, I, O> = Rc<RefCell<Option<Box<dyn Print<E, Input=I, Output=O>>>>>;
+
+/// A reference to a recursive bidirectional parser. See `recursive`.
+pub struct Link<E, I, O>(Slot<E, I, O>);
+
+impl<E, I, O> Pattern<E> for Link<E, I, O>
+    where E: Error<I>
+{
+    type Input = I;
+    type Output = O;
+
+    fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+        self.0.borrow().as_ref().unwrap().parse(stream)
+    }
+
+    fn cloned(&self) -> Self where Self: Sized {
+        Self(self.0.clone())
+    }
+
+    fn describe(&self, grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+        grammar.rule(Rc::as_ptr(&self.0) as *const () as usize, None, |grammar| match self.0.borrow().as_ref() {
+            Some(parser) => parser.describe(grammar),
+            None => Node::Opaque,
+        })
+    }
+}
+
+impl<E, I, O> Print<E> for Link<E, I, O>
+    where E: Error<I>
+{
+    fn print(&self, value: &Self::Output, out: &mut Vec<Self::Input>) -> bool {
+        self.0.borrow().as_ref().unwrap().print(value, out)
+    }
+}
+
+/// Defines a bidirectional parser in terms of itself. `f` is given a reference to the parser it defines.
+pub fn recursive<E, I, O, P>(f: impl FnOnce(Parser<Link<E, I, O>, E>) -> Parser<P, E>) -> Parser<Link<E, I, O>, E>
+    where
+        P: Print<E, Input=I, Output=O> + 'static,
+        E: Error<I>,
+{
+    let slot: Slot<E, I, O> = Rc::new(RefCell::new(None));
+    let parser = f(Parser::from_pat(Link(slot.clone())));
+    *slot.borrow_mut() = Some(Box::new(parser.pat));
+    Parser::from_pat(Link(slot))
+}
@@ -0,0 +1,265 @@
+use std::{
+    convert::TryInto,
+    marker::PhantomData,
+    ops::Range,
+};
+use crate::{
+    primitives::seq,
+    grammar::{Grammar, Node},
+    Parser,
+    Pattern,
+    Error,
+    Stream,
+    ParseResult,
+    Fail,
+};
+
+// Fixed
+
+// Reads exactly `N` bytes and converts them with `f`
+fn fixed<O, E, const N: usize>(f: fn([u8; N]) -> O) -> Parser<impl Pattern<E, Input=u8, Output=O>, E>
+    where E: Error<u8>
+{
+    struct Fixed<O, E, const N: usize>(fn([u8; N]) -> O, PhantomData<E>);
+
+    impl<O, E, const N: usize> Pattern<E> for Fixed<O, E, N>
+        where E: Error<u8>
+    {
+        type Input = u8;
+        type Output = O;
+
+        fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+            match stream.take_slice(N) {
+                Some(bytes) => Ok(((self.0)(bytes.try_into().unwrap()), Fail::none())),
+                None => Err(Fail::one(stream.end_index(), E::unexpected_end(stream.end_span()))),
+            }
+        }
+
+        fn cloned(&self) -> Self where Self: Sized {
+            Self(self.0, PhantomData)
+        }
+
+        fn describe(&self, _grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+            Node::any_n(N)
+        }
+    }
+
+    Parser::from_pat(Fixed(f, PhantomData))
+}
+
+macro_rules! fixed_readers {
+    ($($ty:ident => $le:ident, $be:ident;)*) => {
+        $(
+            #[doc = concat!("Reads a little-endian `", stringify!($ty), "`.")]
+            pub fn $le<E: Error<u8>>() -> Parser<impl Pattern<E, Input=u8, Output=$ty>, E> {
+                fixed($ty::from_le_bytes)
+            }
+
+            #[doc = concat!("Reads a big-endian `", stringify!($ty), "`.")]
+            pub fn $be<E: Error<u8>>() -> Parser<impl Pattern<E, Input=u8, Output=$ty>, E> {
+                fixed($ty::from_be_bytes)
+            }
+        )*
+    };
+}
+
+pub fn u8<E: Error<u8>>() -> Parser<impl Pattern<E, Input=u8, Output=u8>, E> {
+    fixed(|[b]: [u8; 1]| b)
+}
+
+pub fn i8<E: Error<u8>>() -> Parser<impl Pattern<E, Input=u8, Output=i8>, E> {
+    fixed(i8::from_le_bytes)
+}
+
+fixed_readers! {
+    u16 => u16_le, u16_be;
+    i16 => i16_le, i16_be;
+    u32 => u32_le, u32_be;
+    i32 => i32_le, i32_be;
+    u64 => u64_le, u64_be;
+    i64 => i64_le, i64_be;
+    u128 => u128_le, u128_be;
+    i128 => i128_le, i128_be;
+    f32 => f32_le, f32_be;
+    f64 => f64_le, f64_be;
+}
+
+// Leb128
+
+// Reads a LEB128 group, returning the accumulated value, the number of bits read and the final byte
+fn leb128<E: Error<u8>>(stream: &mut Stream<u8>, signed: bool) -> Result<(u64, u32, u8), Fail<E>> {
+    let mut value = 0u64;
+    let mut shift = 0u32;
+    loop {
+        let (idx, byte) = match stream.next() {
+            Some((idx, byte)) => (idx, *byte),
+            None => return Err(Fail::one(stream.end_index(), E::unexpected_end(stream.end_span()))),
+        };
+        // The tenth group holds only the top bit, so the rest of it must be zero, or a copy of the sign if signed
+        let fits = match (shift, byte & 0x7F) {
+            (0..=62, _) => true,
+            (63, 0x00) | (63, 0x01) if !signed => true,
+            (63, 0x00) | (63, 0x7F) if signed => true,
+            _ => false,
+        };
+        if !fits {
+            return Err(Fail::one(idx, E::unexpected_sym(&byte, stream.span_from(idx)).expected_label("LEB128 that fits in 64 bits")));
+        }
+        value |= u64::from(byte & 0x7F) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            break Ok((value, shift, byte));
+        }
+    }
+}
+
+/// Reads an unsigned LEB128 integer, also known as a varint.
+pub fn uleb128<E: Error<u8>>() -> Parser<impl Pattern<E, Input=u8, Output=u64>, E> {
+    struct Uleb128<E>(PhantomData<E>);
+
+    impl<E: Error<u8>> Pattern<E> for Uleb128<E> {
+        type Input = u8;
+        type Output = u64;
+
+        fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+            let mut inner = *stream;
+            let (value, _, _) = leb128(&mut inner, false)?;
+            *stream = inner;
+            Ok((value, Fail::none()))
+        }
+
+        fn cloned(&self) -> Self where Self: Sized {
+            Self(PhantomData)
+        }
+
+        fn describe(&self, _grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+            Node::Label("unsigned LEB128")
+        }
+    }
+
+    Parser::from_pat(Uleb128(PhantomData))
+}
+
+/// Reads a signed LEB128 integer.
+pub fn sleb128<E: Error<u8>>() -> Parser<impl Pattern<E, Input=u8, Output=i64>, E> {
+    struct Sleb128<E>(PhantomData<E>);
+
+    impl<E: Error<u8>> Pattern<E> for Sleb128<E> {
+        type Input = u8;
+        type Output = i64;
+
+        fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+            let mut inner = *stream;
+            let (mut value, shift, last) = leb128(&mut inner, true)?;
+            if shift < 64 && last & 0x40 != 0 {
+                value |= !0 << shift;
+            }
+            *stream = inner;
+            Ok((value as i64, Fail::none()))
+        }
+
+        fn cloned(&self) -> Self where Self: Sized {
+            Self(PhantomData)
+        }
+
+        fn describe(&self, _grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+            Node::Label("signed LEB128")
+        }
+    }
+
+    Parser::from_pat(Sleb128(PhantomData))
+}
+
+// Take
+
+/// Reads exactly `n` bytes, returning the range of the input that they cover so that they can be borrowed from it
+/// rather than copied.
+pub fn take<E: Error<u8>>(n: usize) -> Parser<impl Pattern<E, Input=u8, Output=Range<usize>>, E> {
+    struct Take<E>(usize, PhantomData<E>);
+
+    impl<E: Error<u8>> Pattern<E> for Take<E> {
+        type Input = u8;
+        type Output = Range<usize>;
+
+        fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+            let checkpoint = stream.checkpoint();
+            match stream.take_slice(self.0) {
+                Some(_) => Ok((stream.range_from(checkpoint), Fail::none())),
+                None => Err(Fail::one(stream.end_index(), E::unexpected_end(stream.end_span()))),
+            }
+        }
+
+        fn cloned(&self) -> Self where Self: Sized {
+            Self(self.0, PhantomData)
+        }
+
+        fn describe(&self, _grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+            Node::any_n(self.0)
+        }
+    }
+
+    Parser::from_pat(Take(n, PhantomData))
+}
+
+// Magic
+
+/// Requires the input to continue with exactly the bytes in `magic`, such as a file signature.
+pub fn magic<E: Error<u8>>(magic: impl AsRef<[u8]>) -> Parser<impl Pattern<E, Input=u8, Output=()>, E> {
+    seq(magic.as_ref().to_vec()).to(())
+}
+
+// LengthPrefixed
+
+/// Reads a length with `len`, then runs `inner` over only that many following bytes.
+///
+/// `inner` sees the end of input at the end of the prefixed region. Any bytes it leaves unconsumed are skipped; follow
+/// it with `end()` to reject them instead.
+pub fn length_prefixed<L, P, E>(
+    len: Parser<impl Pattern<E, Input=u8, Output=L>, E>,
+    inner: Parser<P, E>,
+) -> Parser<impl Pattern<E, Input=u8, Output=P::Output>, E>
+    where
+        L: TryInto<usize>,
+        P: Pattern<E, Input=u8>,
+        E: Error<u8>,
+{
+    struct LengthPrefixed<A, B, E>(A, B, PhantomData<E>);
+
+    impl<A, B, L, E> Pattern<E> for LengthPrefixed<A, B, E>
+        where
+            A: Pattern<E, Input=u8, Output=L>,
+            B: Pattern<E, Input=u8>,
+            L: TryInto<usize>,
+            E: Error<u8>,
+    {
+        type Input = u8;
+        type Output = B::Output;
+
+        fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+            let mut outer = *stream;
+            let (len, f) = self.0.parse(&mut outer)?;
+            let (len, mut inner) = match len.try_into().ok().and_then(|len| Some((len, outer.bounded(len)?))) {
+                Some(bounded) => bounded,
+                None => return Err(f.max(Fail::one(stream.end_index(), E::unexpected_end(stream.end_span())))),
+            };
+            let (out, g) = match self.1.parse(&mut inner) {
+                Ok((out, g)) => (out, g),
+                Err(g) => return Err(f.max(g)),
+            };
+            outer.take_slice(len);
+            outer.keep_events(&inner);
+            *stream = outer;
+            Ok((out, f.max(g)))
+        }
+
+        fn cloned(&self) -> Self where Self: Sized {
+            Self(self.0.cloned(), self.1.cloned(), PhantomData)
+        }
+
+        fn describe(&self, grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+            self.0.describe(grammar).then(self.1.describe(grammar))
+        }
+    }
+
+    Parser::from_pat(LengthPrefixed(len.pat, inner.pat, PhantomData))
+}
@@ -0,0 +1,307 @@
+//! Lossless concrete syntax trees.
+//!
+//! Parsers wrapped with `.node(kind)` mark the input they consume as a node of the given kind. Parsing with
+//! `Parser::parse_cst` then produces a tree of these nodes alongside the normal output. Every input symbol appears in
+//! the tree, whether or not it was marked, so the tree always reproduces the input exactly.
+//!
+//! ```ignore
+//! let ws = whitespace().node(Kind::Whitespace);
+//! let num = int(10).node(Kind::Num);
+//! let sum = num.clone().padded_by(ws.clone()).separated_by(just('+').padded_by(ws)).node(Kind::Sum);
+//!
+//! let (out, tree) = sum.parse_cst(Kind::Root, "1 + 2".chars())?;
+//! assert_eq!(tree.collect::<String>(), "1 + 2");
+//! ```
+
+use std::{
+    any::Any,
+    cell::{Cell, Ref, RefCell},
+    iter::FromIterator,
+    marker::PhantomData,
+    ops::Range,
+};
+use crate::{
+    grammar::{self, Grammar},
+    Parser,
+    Pattern,
+    Error,
+    Stream,
+    ParseResult,
+};
+
+/// The kind of a syntax tree node, usually a fieldless enum.
+pub trait Kind: Copy {
+    fn to_raw(self) -> u16;
+    fn from_raw(raw: u16) -> Self;
+}
+
+pub(crate) enum Event {
+    Start(u16),
+    Finish,
+    /// An output passed on to a sink. See `Parser::emitted`.
+    Value(Box<dyn Any>),
+}
+
+impl Event {
+    /// A copy of this event, unless it carries a value.
+    pub(crate) fn copy(&self) -> Option<Self> {
+        match self {
+            Event::Start(kind) => Some(Event::Start(*kind)),
+            Event::Finish => Some(Event::Finish),
+            Event::Value(_) => None,
+        }
+    }
+}
+
+/// A log of events, each along with the stream position at which it occurred.
+///
+/// With a sink, events are passed on to it and forgotten as soon as no choice underway could roll them back. If more
+/// inputs might follow those being parsed, events are held back once the parse looks past them, since it may then be
+/// run again with more.
+#[derive(Default)]
+pub(crate) struct EventLog<'s> {
+    events: RefCell<Vec<(Event, usize)>>,
+    // The number of events already passed to the sink, which are no longer in `events`
+    flushed: Cell<usize>,
+    // The number of events that each choice underway keeps if it fails, innermost last. A choice that has passed a cut
+    // keeps everything, since its failure is committed and so is not followed by any other alternative.
+    rewinds: RefCell<Vec<Option<usize>>>,
+    sink: Option<RefCell<&'s mut dyn FnMut(Event, usize)>>,
+    // The number of inputs pulled so far, if more might follow them
+    pulled: Option<usize>,
+    // Whether the parse has looked past the inputs pulled so far
+    held: Cell<bool>,
+}
+
+impl<'s> EventLog<'s> {
+    /// A log that passes events on to `sink`, for a parse of the first `pulled` inputs if more might follow them.
+    pub(crate) fn with_sink(sink: &'s mut dyn FnMut(Event, usize), pulled: Option<usize>) -> Self {
+        Self { sink: Some(RefCell::new(sink)), pulled, ..Self::default() }
+    }
+
+    /// Writes an event after the first `len`, discarding any that came after them.
+    pub(crate) fn push(&self, len: usize, event: Event, pos: usize) {
+        {
+            let mut events = self.events.borrow_mut();
+            events.truncate(len.saturating_sub(self.flushed.get()));
+            events.push((event, pos));
+        }
+        self.flush(len + 1);
+    }
+
+    /// Starts a choice, which keeps the first `len` events if it fails.
+    pub(crate) fn enter_choice(&self, len: usize) {
+        self.rewinds.borrow_mut().push(Some(len));
+    }
+
+    /// Settles a choice, after which the first `len` events are kept.
+    pub(crate) fn leave_choice(&self, len: usize) {
+        self.rewinds.borrow_mut().pop();
+        self.flush(len);
+    }
+
+    /// Marks the innermost choice as unable to roll back the first `len` events, or any that follow them.
+    pub(crate) fn cut(&self, len: usize) {
+        if let Some(rewind) = self.rewinds.borrow_mut().last_mut() {
+            *rewind = None;
+        }
+        self.flush(len);
+    }
+
+    /// Passes as many of the first `len` events on to the sink as no choice underway could roll back, if there is a
+    /// sink.
+    pub(crate) fn flush(&self, len: usize) {
+        if let Some(sink) = self.sink.as_ref().filter(|_| !self.held.get()) {
+            let len = self.rewinds.borrow().iter().find_map(|rewind| *rewind).map_or(len, |rewind| len.min(rewind));
+            let n = len.saturating_sub(self.flushed.get());
+            let events = self.events.borrow_mut().drain(..n).collect::<Vec<_>>();
+            self.flushed.set(self.flushed.get() + n);
+            let mut sink = sink.borrow_mut();
+            for (event, pos) in events {
+                sink(event, pos);
+            }
+        }
+    }
+
+    /// Notes that the parse has looked for inputs beyond the first `end`.
+    pub(crate) fn reached(&self, end: usize) {
+        if self.pulled.is_some_and(|pulled| end >= pulled) {
+            self.held.set(true);
+        }
+    }
+
+    /// Whether the parse has looked past the inputs pulled so far, so that more might change its outcome.
+    pub(crate) fn is_held(&self) -> bool {
+        self.held.get()
+    }
+
+    pub(crate) fn has_sink(&self) -> bool {
+        self.sink.is_some()
+    }
+
+    /// Copies of the events in `range` that are still held, leaving out values.
+    pub(crate) fn copy(&self, range: Range<usize>) -> Vec<(Event, usize)> {
+        let flushed = self.flushed.get();
+        let events = self.events.borrow();
+        events[range.start.max(flushed) - flushed..range.end.max(flushed) - flushed]
+            .iter()
+            .filter_map(|(event, pos)| Some((event.copy()?, *pos)))
+            .collect()
+    }
+
+    pub(crate) fn events(&self) -> Ref<'_, Vec<(Event, usize)>> {
+        self.events.borrow()
+    }
+}
+
+type CstResult<O, K, T, E> = Result<(O, GreenNode<K, T>), Vec<E>>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GreenElement<K, T> {
+    Node(GreenNode<K, T>),
+    /// A run of symbols between nodes.
+    Token(Vec<T>),
+}
+
+/// A node in a lossless syntax tree.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GreenNode<K, T> {
+    kind: K,
+    children: Vec<GreenElement<K, T>>,
+    len: usize,
+}
+
+impl<K: Copy, T: Clone> GreenNode<K, T> {
+    pub fn kind(&self) -> K {
+        self.kind
+    }
+
+    pub fn children(&self) -> &[GreenElement<K, T>] {
+        &self.children
+    }
+
+    /// The number of symbols covered by this node.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Collects the symbols covered by this node, such as into a `String`.
+    pub fn collect<C: FromIterator<T>>(&self) -> C {
+        fn walk<K, T: Clone>(node: &GreenNode<K, T>, syms: &mut Vec<T>) {
+            for child in &node.children {
+                match child {
+                    GreenElement::Node(node) => walk(node, syms),
+                    GreenElement::Token(toks) => syms.extend(toks.iter().cloned()),
+                }
+            }
+        }
+
+        let mut syms = Vec::with_capacity(self.len);
+        walk(self, &mut syms);
+        syms.into_iter().collect()
+    }
+}
+
+// Builds a tree from a log of events, filling the gaps between them with the symbols of `syms`
+fn build<K: Kind, T: Clone>(root: K, syms: &[T], events: &[(Event, usize)]) -> GreenNode<K, T> {
+    fn push_token<K, T: Clone>(node: &mut GreenNode<K, T>, syms: &[T]) {
+        if !syms.is_empty() {
+            node.children.push(GreenElement::Token(syms.to_vec()));
+            node.len += syms.len();
+        }
+    }
+
+    let mut stack = vec![GreenNode { kind: root, children: Vec::new(), len: 0 }];
+    let mut pos = 0;
+
+    for (event, idx) in events {
+        if let Event::Value(_) = event {
+            continue;
+        }
+        push_token(stack.last_mut().unwrap(), &syms[pos..*idx]);
+        pos = *idx;
+        match event {
+            Event::Start(kind) => stack.push(GreenNode { kind: K::from_raw(*kind), children: Vec::new(), len: 0 }),
+            Event::Finish => {
+                let node = stack.pop().unwrap();
+                let parent = stack.last_mut().unwrap();
+                parent.len += node.len;
+                parent.children.push(GreenElement::Node(node));
+            },
+            Event::Value(_) => {},
+        }
+    }
+    let mut root = stack.pop().unwrap();
+    push_token(&mut root, &syms[pos..]);
+    root
+}
+
+// Node
+
+impl<P, E> Parser<P, E> {
+    /// Marks the input consumed by this parser as a syntax tree node of the given kind.
+    ///
+    /// This has no effect unless the parser is run with `parse_cst`.
+    pub fn node<K: Kind>(self, kind: K) -> Parser<impl Pattern<E, Input=P::Input, Output=P::Output>, E>
+        where
+            P: Pattern<E>,
+            E: Error<P::Input>,
+    {
+        struct Node<A, E>(u16, A, PhantomData<E>);
+
+        impl<A, E> Pattern<E> for Node<A, E>
+            where
+                A: Pattern<E>,
+                E: Error<A::Input>,
+        {
+            type Input = A::Input;
+            type Output = A::Output;
+
+            fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+                let mut inner = *stream;
+                inner.emit(Event::Start(self.0));
+                let (out, fail) = self.1.parse(&mut inner)?;
+                inner.emit(Event::Finish);
+                *stream = inner;
+                Ok((out, fail))
+            }
+
+            fn cloned(&self) -> Self where Self: Sized {
+                Self(self.0, self.1.cloned(), PhantomData)
+            }
+
+            fn describe(&self, grammar: &mut Grammar<E::Thing>) -> grammar::Node<E::Thing> {
+                self.1.describe(grammar)
+            }
+        }
+
+        Parser::from_pat(Node(kind.to_raw(), self.pat, PhantomData))
+    }
+
+    /// Parses `inputs`, producing a lossless syntax tree rooted at a node of kind `root` alongside the output.
+    ///
+    /// Input that the parser does not consume still appears at the end of the tree.
+    pub fn parse_cst<I, K>(&self, root: K, inputs: I) -> CstResult<P::Output, K, P::Input, E>
+        where
+            P: Pattern<E>,
+            P::Input: Clone,
+            I: IntoIterator<Item=P::Input>,
+            K: Kind,
+            E: Error<P::Input>,
+    {
+        let inputs = inputs.into_iter().collect::<Vec<_>>();
+        let events = EventLog::default();
+        let mut stream = Stream::with_events(&inputs, &events);
+        match self.parse_stream(&mut stream) {
+            Ok((out, _)) => {
+                let events = events.events();
+                Ok((out, build(root, &inputs, &events[..stream.events_len()])))
+            },
+            Err(fail) => Err(fail.collect()),
+        }
+    }
+}
@@ -9,23 +9,42 @@ use crate::{
     Error,
     Stream,
     ParseResult,
+    grammar::{Grammar, Node},
 };
 
+type Slot<E, I, O> = Rc<RefCell<Option<Box<dyn Pattern<E, Input=I, Output=O>>>>>;
+
 pub struct Declaration<E, I, O> {
-    parser: Rc<RefCell<Option<Box<dyn Pattern<E, Input=I, Output=O>>>>>,
+    parser: Slot<E, I, O>,
+    name: Option<&'static str>,
 }
 
 impl<E, I, O> Default for Declaration<E, I, O> {
     fn default() -> Self {
-        Self { parser: Rc::new(RefCell::new(None)) }
+        Self { parser: Rc::new(RefCell::new(None)), name: None }
     }
 }
 
+// Describes a declared parser as a reference to its rule, keyed by the address of its slot
+fn describe_rule<E, I, O>(slot: &Slot<E, I, O>, name: Option<&'static str>, grammar: &mut Grammar<E::Thing>) -> Node<E::Thing>
+    where E: Error<I>
+{
+    grammar.rule(Rc::as_ptr(slot) as *const () as usize, name, |grammar| match slot.borrow().as_ref() {
+        Some(parser) => parser.describe(grammar),
+        None => Node::Opaque,
+    })
+}
+
 impl<E, I, O> Declaration<E, I, O>
     where E: Error<I>
 {
+    /// Gives this declaration a name, used for its rule when describing a grammar (see `Parser::grammar`).
+    pub fn named(self, name: &'static str) -> Self {
+        Self { name: Some(name), ..self }
+    }
+
     pub fn link(&self) -> Parser<impl Pattern<E, Input=I, Output=O>, E> {
-        struct Linked<I, O, E>(Rc<RefCell<Option<Box<dyn Pattern<E, Input=I, Output=O>>>>>, PhantomData<(I, O)>);
+        struct Linked<I, O, E>(Slot<E, I, O>, Option<&'static str>, PhantomData<(I, O)>);
 
         impl<I, O, E> Pattern<E> for Linked<I, O, E>
             where E: Error<I>
@@ -38,15 +57,25 @@ impl<E, I, O> Declaration<E, I, O>
             }
 
             fn cloned(&self) -> Self where Self: Sized {
-                Self(self.0.clone(), PhantomData)
+                Self(self.0.clone(), self.1, PhantomData)
+            }
+
+            fn describe(&self, grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+                describe_rule(&self.0, self.1, grammar)
             }
         }
 
-        Parser::from_pat(Linked(self.parser.clone(), PhantomData))
+        Parser::from_pat(Linked(self.parser.clone(), self.name, PhantomData))
     }
 
     pub fn define(self, parser: Parser<impl Pattern<E, Input=I, Output=O> + 'static, E>) -> Parser<impl Pattern<E, Input=I, Output=O>, E> {
-        struct Defined<I, O, E>(Rc<RefCell<Option<Box<dyn Pattern<E, Input=I, Output=O>>>>>);
+        self.define_shared(parser)
+    }
+
+    // Used by `parsers!`, where the bodies of later rules may still link to this one
+    #[doc(hidden)]
+    pub fn define_shared(&self, parser: Parser<impl Pattern<E, Input=I, Output=O> + 'static, E>) -> Parser<impl Pattern<E, Input=I, Output=O>, E> {
+        struct Defined<I, O, E>(Slot<E, I, O>, Option<&'static str>);
 
         impl<I, O, E> Pattern<E> for Defined<I, O, E>
             where E: Error<I>
@@ -59,13 +88,17 @@ impl<E, I, O> Declaration<E, I, O>
             }
 
             fn cloned(&self) -> Self where Self: Sized {
-                Self(self.0.clone())
+                Self(self.0.clone(), self.1)
+            }
+
+            fn describe(&self, grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+                describe_rule(&self.0, self.1, grammar)
             }
         }
 
         *self.parser.borrow_mut() = Some(Box::new(parser.pat));
 
-        Parser::from_pat(Defined(self.parser))
+        Parser::from_pat(Defined(self.parser.clone(), self.name))
     }
 }
 
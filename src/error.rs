@@ -13,9 +13,10 @@ pub trait Error<S>: Sized {
     type Context;
 
     fn unexpected_sym(sym: &S, at: Self::Span) -> Self;
-    fn unexpected_end() -> Self;
+    fn unexpected_end(at: Self::Span) -> Self;
     fn expected_end(sym: &S, at: Self::Span) -> Self;
     fn expected(self, _sym: Self::Thing) -> Self { self }
+    fn expected_label(self, _label: &'static str) -> Self { self }
     fn merge(self, _other: Self) -> Self { self }
     fn context(self, _ctx: Self::Context) -> Self { self }
 }
@@ -36,7 +37,7 @@ impl<S> Error<S> for EmptyError<S> {
         Self(PhantomData)
     }
 
-    fn unexpected_end() -> Self {
+    fn unexpected_end(_at: Self::Span) -> Self {
         Self(PhantomData)
     }
 
@@ -72,6 +73,26 @@ pub struct SimpleError<S> {
     found: Option<S>,
     at: Option<Option<Range<usize>>>,
     expected: Option<HashSet<S>>,
+    labels: HashSet<&'static str>,
+}
+
+impl<S> SimpleError<S> {
+    pub fn found(&self) -> Option<&S> {
+        self.found.as_ref()
+    }
+
+    pub fn span(&self) -> Option<Range<usize>> {
+        self.at.clone().flatten()
+    }
+
+    /// The set of symbols that would have been accepted, or `None` if only the end of input was expected.
+    pub fn expected_set(&self) -> Option<&HashSet<S>> {
+        self.expected.as_ref()
+    }
+
+    pub fn labels(&self) -> &HashSet<&'static str> {
+        &self.labels
+    }
 }
 
 impl<S: Hash + Eq + Clone> Error<S> for SimpleError<S> {
@@ -84,14 +105,16 @@ impl<S: Hash + Eq + Clone> Error<S> for SimpleError<S> {
             found: Some(sym.clone()),
             at: Some(at),
             expected: Some(HashSet::default()),
+            labels: HashSet::default(),
         }
     }
 
-    fn unexpected_end() -> Self {
+    fn unexpected_end(at: Self::Span) -> Self {
         Self {
             found: None,
-            at: None,
+            at: Some(at),
             expected: Some(HashSet::default()),
+            labels: HashSet::default(),
         }
     }
 
@@ -100,6 +123,7 @@ impl<S: Hash + Eq + Clone> Error<S> for SimpleError<S> {
             found: Some(sym.clone()),
             at: Some(at),
             expected: None,
+            labels: HashSet::default(),
         }
     }
 
@@ -108,6 +132,11 @@ impl<S: Hash + Eq + Clone> Error<S> for SimpleError<S> {
         self
     }
 
+    fn expected_label(mut self, label: &'static str) -> Self {
+        self.labels.insert(label);
+        self
+    }
+
     fn merge(self, other: Self) -> Self {
         Self {
             found: self.found.or(other.found),
@@ -119,6 +148,7 @@ impl<S: Hash + Eq + Clone> Error<S> for SimpleError<S> {
                     .chain(other.expected.map(|e| e.into_iter()).into_iter().flatten())
                     .collect())
             },
+            labels: self.labels.into_iter().chain(other.labels).collect(),
         }
     }
 }
@@ -130,8 +160,23 @@ impl<S: Hash + Eq + fmt::Debug> fmt::Debug for SimpleError<S> {
             None => write!(f, "Found end of input")?,
         }
         match &self.expected {
-            Some(expected) => write!(f, ", expected {:?}", expected),
-            None => write!(f, ", expected end of input"),
+            Some(expected) => write!(f, ", expected {:?}", expected)?,
+            None => write!(f, ", expected end of input")?,
         }
+        if !self.labels.is_empty() {
+            write!(f, " ({:?})", self.labels)?;
+        }
+        Ok(())
     }
 }
+
+// PipelineError
+
+/// An error produced by `Parser::parse_lexed`.
+#[derive(Debug, PartialEq)]
+pub enum PipelineError<L, P> {
+    /// The source text could not be split into tokens.
+    Lex(Vec<L>),
+    /// The tokens could not be parsed.
+    Parse(Vec<P>),
+}
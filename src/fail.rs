@@ -7,10 +7,23 @@ use crate::{
     Error,
 };
 
+// Where a failure occurred: its index in the stream, then its index within each nested parse it occurred in, outermost
+// first. Failures at the same symbol are ordered by how far they got into it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Pos(pub Index, Vec<Index>);
+
+impl From<Index> for Pos {
+    fn from(idx: Index) -> Self {
+        Pos(idx, Vec::new())
+    }
+}
+
 pub enum Fail<E> {
     None,
-    One(Index, E),
-    Group(Index, Vec<E>),
+    One(Pos, E),
+    Group(Pos, Vec<E>),
+    // A failure after a cut, which no alternative may recover from
+    Committed(Pos, Vec<E>),
 }
 
 impl<E> Fail<E> {
@@ -19,7 +32,7 @@ impl<E> Fail<E> {
     }
 
     pub fn one(idx: Index, err: E) -> Self {
-        Fail::One(idx, err)
+        Fail::One(idx.into(), err)
     }
 
     pub fn map<D>(self, f: impl Fn(E) -> D) -> Fail<D> {
@@ -27,23 +40,42 @@ impl<E> Fail<E> {
             Fail::None => Fail::None,
             Fail::One(idx, err) => Fail::One(idx, f(err)),
             Fail::Group(idx, errs) => Fail::Group(idx, errs.into_iter().map(f).collect()),
+            Fail::Committed(idx, errs) => Fail::Committed(idx, errs.into_iter().map(f).collect()),
         }
     }
 
-    pub fn add_index(mut self, offset: Index) -> Self {
+    /// Moves this failure to `at`, such as when it occurred within a single symbol's nested parse. Where it was within
+    /// that parse is kept, to tell it apart from other failures at `at`.
+    pub fn at(mut self, at: Index) -> Self {
         match &mut self {
             Fail::None => {},
-            Fail::One(idx, _) => *idx = idx.saturating_add(offset),
-            Fail::Group(idx, _) => *idx = idx.saturating_add(offset),
+            Fail::One(pos, _) | Fail::Group(pos, _) | Fail::Committed(pos, _) => {
+                pos.1.insert(0, pos.0);
+                pos.0 = at;
+            },
         }
         self
     }
 
-    fn furthest_idx(&self) -> Index {
+    /// Makes this failure fatal to any enclosing alternatives, since it came after a cut. A failure without any errors
+    /// is left as it is, since it has nothing to report in place of the alternatives.
+    pub fn commit(self) -> Self {
+        match self {
+            Fail::None => Fail::None,
+            Fail::One(idx, err) => Fail::Committed(idx, vec![err]),
+            Fail::Group(idx, errs) => Fail::Committed(idx, errs),
+            this @ Fail::Committed(_, _) => this,
+        }
+    }
+
+    pub fn is_committed(&self) -> bool {
+        matches!(self, Fail::Committed(_, _))
+    }
+
+    pub(crate) fn furthest(&self) -> Option<&Pos> {
         match self {
-            Fail::None => 0,
-            Fail::One(idx, _) => *idx,
-            Fail::Group(idx, _) => *idx,
+            Fail::None => None,
+            Fail::One(pos, _) | Fail::Group(pos, _) | Fail::Committed(pos, _) => Some(pos),
         }
     }
 
@@ -56,7 +88,13 @@ impl<E> Fail<E> {
                 Ordering::Less => Fail::One(b_idx, b_err),
                 Ordering::Equal => Fail::One(a_idx, a_err.merge(b_err)),
             }
-            (this, other) => if this.furthest_idx() > other.furthest_idx() {
+            // A committed failure is reported in place of anything that was expected elsewhere
+            (this, other) if this.is_committed() != other.is_committed() => if this.is_committed() {
+                this
+            } else {
+                other
+            },
+            (this, other) => if this.furthest() > other.furthest() {
                 this
             } else {
                 other
@@ -68,7 +106,7 @@ impl<E> Fail<E> {
         match self {
             Fail::None => I::from_iter(None),
             Fail::One(_, err) => I::from_iter(Some(err)),
-            Fail::Group(_, errs) => I::from_iter(errs),
+            Fail::Group(_, errs) | Fail::Committed(_, errs) => I::from_iter(errs),
         }
     }
 }
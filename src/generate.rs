@@ -0,0 +1,289 @@
+//! Generation of random inputs from a parser's grammar, for fuzzing and property testing.
+//!
+//! ```ignore
+//! let gen = expr.generator().with_text_labels();
+//! let mut rng = XorShift::new(42);
+//! for _ in 0..1000 {
+//!     let input = gen.generate(&mut rng, 8).unwrap();
+//!     assert!(expr.parse_complete(input.clone()).is_ok());
+//!     let _ = expr.parse(gen.mutate(&mut rng, &input)); // Must not panic
+//! }
+//! ```
+//!
+//! Inputs are generated from the parser's description (see the `grammar` module), which says nothing of arbitrary
+//! functions or the order in which alternatives are tried. A parser may therefore reject some inputs, such as when an
+//! earlier alternative accepts a prefix of a later one. Labelled patterns, like `ident`, produce nothing unless given
+//! a generator with `Generator::with_label`, and the parts of a grammar that need them are avoided.
+
+use std::collections::HashMap;
+use crate::{
+    Parser,
+    Pattern,
+    Error,
+    grammar::{Grammar, Node, Thing},
+};
+
+/// A source of random numbers. Implemented for closures, so that any random number generator may be used.
+pub trait Rng {
+    fn next_u64(&mut self) -> u64;
+
+    /// A random number less than `n`, which must not be zero.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+impl<F: FnMut() -> u64> Rng for F {
+    fn next_u64(&mut self) -> u64 {
+        self()
+    }
+}
+
+/// A small, fast, deterministic random number generator.
+#[derive(Clone, Debug)]
+pub struct XorShift(u64);
+
+impl XorShift {
+    pub fn new(seed: u64) -> Self {
+        // The state must never be zero
+        Self((seed ^ 0x9E37_79B9_7F4A_7C15).max(1))
+    }
+}
+
+impl Rng for XorShift {
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+type LabelFn<T> = Box<dyn Fn(&mut dyn Rng) -> Vec<T>>;
+
+// Repetitions produce up to this many items
+const MAX_REPEATS: usize = 3;
+const UNREACHABLE: usize = usize::MAX;
+
+/// Generates inputs from a grammar. See `Parser::generator`.
+pub struct Generator<T> {
+    grammar: Grammar<T>,
+    labels: HashMap<&'static str, LabelFn<T>>,
+    alphabet: Vec<T>,
+    // The fewest rule expansions needed to generate anything from each rule
+    costs: HashMap<String, usize>,
+}
+
+impl<T: Clone + PartialEq> Generator<T> {
+    /// Generates inputs from the first rule of `grammar`.
+    ///
+    /// Symbols for `any` and `none_of` are drawn from the alphabet, which is initially every symbol that appears in
+    /// the grammar.
+    pub fn new(grammar: Grammar<T>) -> Self {
+        let mut alphabet = Vec::new();
+        for (_, node) in grammar.rules() {
+            symbols(node, &mut alphabet);
+        }
+        let mut this = Self { grammar, labels: HashMap::new(), alphabet, costs: HashMap::new() };
+        this.update_costs();
+        this
+    }
+
+    /// Generates the inputs for patterns labelled `label`, such as `"identifier"`, with `f`.
+    pub fn with_label(mut self, label: &'static str, f: impl Fn(&mut dyn Rng) -> Vec<T> + 'static) -> Self {
+        self.labels.insert(label, Box::new(f));
+        self.update_costs();
+        self
+    }
+
+    /// Draws symbols for `any` and `none_of` from `alphabet`, in place of those found in the grammar.
+    pub fn with_alphabet(mut self, alphabet: impl IntoIterator<Item=T>) -> Self {
+        self.alphabet = alphabet.into_iter().collect();
+        self.update_costs();
+        self
+    }
+
+    /// Generates a random input, expanding rules no more than `depth` deep within the first.
+    ///
+    /// Returns `None` if nothing can be generated within that depth.
+    pub fn generate(&self, rng: &mut dyn Rng, depth: usize) -> Option<Vec<T>> {
+        let (_, root) = self.grammar.rules().first()?;
+        if self.cost(root) > depth {
+            return None;
+        }
+        let mut out = Vec::new();
+        self.gen(root, rng, depth, &mut out);
+        Some(out)
+    }
+
+    /// Makes a small random change to `input`, such as deleting, duplicating, swapping or replacing a symbol.
+    ///
+    /// The result is usually, but not always, invalid.
+    pub fn mutate(&self, rng: &mut dyn Rng, input: &[T]) -> Vec<T> {
+        let mut out = input.to_vec();
+        let random = |rng: &mut dyn Rng| self.alphabet[rng.below(self.alphabet.len())].clone();
+        match (rng.below(5), out.len()) {
+            (_, 0) | (0, _) if !self.alphabet.is_empty() => {
+                let pos = rng.below(out.len() + 1);
+                out.insert(pos, random(rng));
+            },
+            (_, 0) => {},
+            (1, len) => { out.remove(rng.below(len)); },
+            (2, len) => {
+                let pos = rng.below(len);
+                out.insert(pos, out[pos].clone());
+            },
+            (3, len) if len > 1 => {
+                let pos = rng.below(len - 1);
+                out.swap(pos, pos + 1);
+            },
+            (_, len) if !self.alphabet.is_empty() => out[rng.below(len)] = random(rng),
+            (_, len) => { out.remove(rng.below(len)); },
+        }
+        out
+    }
+
+    fn update_costs(&mut self) {
+        self.costs = self.grammar.rules().iter().map(|(name, _)| (name.clone(), UNREACHABLE)).collect();
+        // Costs only ever fall, so this settles after at most one pass per rule
+        loop {
+            let mut changed = false;
+            for (name, node) in self.grammar.rules() {
+                let cost = self.cost(node).saturating_add(1);
+                if cost < self.costs[name] {
+                    self.costs.insert(name.clone(), cost);
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    // The fewest rule expansions needed to generate anything from `node`
+    fn cost(&self, node: &Node<T>) -> usize {
+        match node {
+            Node::Opaque => UNREACHABLE,
+            Node::Any if self.alphabet.is_empty() => UNREACHABLE,
+            Node::NoneOf(ts) if self.alphabet.iter().all(|t| ts.contains(t)) => UNREACHABLE,
+            Node::OneOf(ts) if ts.is_empty() => UNREACHABLE,
+            Node::Any | Node::End | Node::Just(_) | Node::Seq(_) | Node::OneOf(_) | Node::NoneOf(_) => 0,
+            Node::Label(label) if self.labels.contains_key(label) => 0,
+            Node::Label(_) => UNREACHABLE,
+            Node::Sequence(nodes) => nodes.iter().map(|node| self.cost(node)).max().unwrap_or(0),
+            Node::Choice(nodes) => nodes.iter().map(|node| self.cost(node)).min().unwrap_or(UNREACHABLE),
+            Node::Repeated(_) | Node::Optional(_) | Node::SeparatedBy(_, _) => 0,
+            Node::OnceOrMore(node) => self.cost(node),
+            Node::Rule(name) => self.costs.get(name).copied().unwrap_or(UNREACHABLE),
+        }
+    }
+
+    // How many times to repeat `node`: at least `min`, or none at all if it is out of reach
+    fn repeats(&self, node: &Node<T>, min: usize, rng: &mut dyn Rng, depth: usize) -> usize {
+        if self.cost(node) <= depth {
+            min + rng.below(MAX_REPEATS + 1 - min)
+        } else {
+            0
+        }
+    }
+
+    fn gen(&self, node: &Node<T>, rng: &mut dyn Rng, depth: usize, out: &mut Vec<T>) {
+        match node {
+            Node::Opaque | Node::End => {},
+            Node::Any => out.push(self.alphabet[rng.below(self.alphabet.len())].clone()),
+            Node::Just(t) => out.push(t.clone()),
+            Node::Seq(ts) => out.extend(ts.iter().cloned()),
+            Node::OneOf(ts) => out.push(ts[rng.below(ts.len())].clone()),
+            Node::NoneOf(ts) => {
+                let allowed = self.alphabet.iter().filter(|t| !ts.contains(t)).collect::<Vec<_>>();
+                out.push(allowed[rng.below(allowed.len())].clone());
+            },
+            Node::Label(label) => out.extend(self.labels[label](rng)),
+            Node::Sequence(nodes) => for node in nodes {
+                self.gen(node, rng, depth, out);
+            },
+            Node::Choice(nodes) => {
+                let reachable = nodes.iter().filter(|node| self.cost(node) <= depth).collect::<Vec<_>>();
+                self.gen(reachable[rng.below(reachable.len())], rng, depth, out);
+            },
+            Node::Repeated(node) => for _ in 0..self.repeats(node, 0, rng, depth) {
+                self.gen(node, rng, depth, out);
+            },
+            Node::OnceOrMore(node) => for _ in 0..self.repeats(node, 1, rng, depth) {
+                self.gen(node, rng, depth, out);
+            },
+            Node::Optional(node) => if self.repeats(node, 0, rng, depth) > 0 {
+                self.gen(node, rng, depth, out);
+            },
+            Node::SeparatedBy(item, sep) => {
+                let n = if self.cost(sep) <= depth { self.repeats(item, 0, rng, depth) } else { self.repeats(item, 0, rng, depth).min(1) };
+                for i in 0..n {
+                    if i > 0 {
+                        self.gen(sep, rng, depth, out);
+                    }
+                    self.gen(item, rng, depth, out);
+                }
+            },
+            Node::Rule(name) => {
+                let (_, body) = self.grammar.rules().iter().find(|(rule, _)| rule == name).unwrap();
+                self.gen(body, rng, depth - 1, out);
+            },
+        }
+    }
+}
+
+impl Generator<char> {
+    /// Generates inputs for the labels used by the parsers in the `text` module, other than `keyword`.
+    pub fn with_text_labels(self) -> Self {
+        fn pick(rng: &mut dyn Rng, chars: &str) -> char {
+            chars.chars().nth(rng.below(chars.chars().count())).unwrap()
+        }
+        fn digits(rng: &mut dyn Rng) -> Vec<char> {
+            (0..1 + rng.below(3)).map(|_| pick(rng, "0123456789")).collect()
+        }
+
+        self
+            .with_label("whitespace", |rng| (0..rng.below(3)).map(|_| pick(rng, " \t\n")).collect())
+            .with_label("newline", |_| vec!['\n'])
+            .with_label("identifier", |rng| {
+                let mut ident = vec![pick(rng, "abcdefghijklmnopqrstuvwxyz_")];
+                ident.extend((0..rng.below(6)).map(|_| pick(rng, "abcdefghijklmnopqrstuvwxyz_0123456789")));
+                ident
+            })
+            .with_label("integer", digits)
+            .with_label("float", |rng| {
+                let mut float = digits(rng);
+                if rng.below(2) == 0 {
+                    float.push('.');
+                    float.extend(digits(rng));
+                }
+                float
+            })
+    }
+}
+
+// Collects the symbols that appear in `node`
+fn symbols<T: Clone + PartialEq>(node: &Node<T>, out: &mut Vec<T>) {
+    let mut add = |t: &T| if !out.contains(t) { out.push(t.clone()) };
+    match node {
+        Node::Just(t) => add(t),
+        Node::Seq(ts) | Node::OneOf(ts) | Node::NoneOf(ts) => ts.iter().for_each(add),
+        Node::Sequence(nodes) | Node::Choice(nodes) => nodes.iter().for_each(|node| symbols(node, out)),
+        Node::Repeated(node) | Node::OnceOrMore(node) | Node::Optional(node) => symbols(node, out),
+        Node::SeparatedBy(item, sep) => { symbols(item, out); symbols(sep, out); },
+        _ => {},
+    }
+}
+
+impl<P, E> Parser<P, E> {
+    /// Creates a generator of random inputs for this parser. See the `generate` module.
+    pub fn generator(&self) -> Generator<Thing<E, P::Input>>
+        where
+            P: Pattern<E>,
+            E: Error<P::Input>,
+            E::Thing: Clone + PartialEq,
+    {
+        Generator::new(self.grammar("start"))
+    }
+}
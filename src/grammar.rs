@@ -0,0 +1,321 @@
+//! Grammar introspection.
+//!
+//! Most combinators can describe their structure with `Pattern::describe`. `Parser::grammar` collects these
+//! descriptions into a `Grammar`, with a rule for each declaration (see `Declaration::named`), which can be exported
+//! as EBNF text or as an SVG railroad diagram.
+//!
+//! ```ignore
+//! let grammar = expr.grammar("expr");
+//! println!("{}", grammar.to_ebnf());
+//! std::fs::write("grammar.svg", grammar.to_svg())?;
+//! ```
+//!
+//! Patterns that test symbols with arbitrary functions, such as `permit` and `nested_parse`, are described as accepting
+//! any symbol. Parts of a parser that cannot be described at all, such as `map_err`, appear as `<?>`.
+
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Write},
+};
+use crate::{
+    Parser,
+    Pattern,
+    Error,
+};
+
+/// The type of symbol used to describe patterns parsing `I` with the error `E`.
+pub type Thing<E, I> = <E as Error<I>>::Thing;
+
+/// A description of a pattern.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node<T> {
+    /// A pattern that cannot be described.
+    Opaque,
+    Any,
+    End,
+    Just(T),
+    Seq(Vec<T>),
+    OneOf(Vec<T>),
+    NoneOf(Vec<T>),
+    /// A pattern described only by a label, such as an identifier or a regular expression.
+    Label(&'static str),
+    /// Patterns that follow one another.
+    Sequence(Vec<Node<T>>),
+    /// Alternative patterns, tried in order.
+    Choice(Vec<Node<T>>),
+    Repeated(Box<Node<T>>),
+    OnceOrMore(Box<Node<T>>),
+    Optional(Box<Node<T>>),
+    /// Repetitions of the first pattern, separated by the second.
+    SeparatedBy(Box<Node<T>>, Box<Node<T>>),
+    /// A reference to the rule of the given name.
+    Rule(String),
+}
+
+impl<T> Node<T> {
+    /// Both patterns in sequence, flattening nested sequences.
+    pub fn then(self, other: Self) -> Self {
+        match (self, other) {
+            (Node::Sequence(mut a), Node::Sequence(b)) => { a.extend(b); Node::Sequence(a) },
+            (Node::Sequence(mut a), b) => { a.push(b); Node::Sequence(a) },
+            (a, Node::Sequence(mut b)) => { b.insert(0, a); Node::Sequence(b) },
+            (a, b) => Node::Sequence(vec![a, b]),
+        }
+    }
+
+    /// Exactly `n` symbols of any kind.
+    pub(crate) fn any_n(n: usize) -> Self {
+        match n {
+            1 => Node::Any,
+            n => Node::Sequence((0..n).map(|_| Node::Any).collect()),
+        }
+    }
+
+    /// Either pattern, flattening nested choices.
+    pub fn or(self, other: Self) -> Self {
+        match (self, other) {
+            (Node::Choice(mut a), Node::Choice(b)) => { a.extend(b); Node::Choice(a) },
+            (Node::Choice(mut a), b) => { a.push(b); Node::Choice(a) },
+            (a, Node::Choice(mut b)) => { b.insert(0, a); Node::Choice(b) },
+            (a, b) => Node::Choice(vec![a, b]),
+        }
+    }
+}
+
+/// A set of named rules, each described by a `Node`.
+pub struct Grammar<T> {
+    rules: Vec<(String, Node<T>)>,
+    // The name given to each declaration seen so far, keyed by its address
+    names: HashMap<usize, String>,
+}
+
+impl<T> Default for Grammar<T> {
+    fn default() -> Self {
+        Self { rules: Vec::new(), names: HashMap::new() }
+    }
+}
+
+impl<T> Grammar<T> {
+    /// Refers to the rule for the declaration with the given address, describing it with `describe` if it has not
+    /// been seen before.
+    pub(crate) fn rule(&mut self, id: usize, name: Option<&'static str>, describe: impl FnOnce(&mut Self) -> Node<T>) -> Node<T> {
+        if let Some(name) = self.names.get(&id) {
+            return Node::Rule(name.clone());
+        }
+        let name = name.map(String::from).unwrap_or_else(|| format!("rule{}", self.names.len() + 1));
+        self.names.insert(id, name.clone());
+        // Reserve the rule's place first, so that rules appear in the order in which they are first referred to
+        let idx = self.rules.len();
+        self.rules.push((name.clone(), Node::Opaque));
+        self.rules[idx].1 = describe(self);
+        Node::Rule(name)
+    }
+
+    pub fn rules(&self) -> &[(String, Node<T>)] {
+        &self.rules
+    }
+
+    /// Writes the grammar as EBNF, with one `name ::= ... ;` rule per line.
+    pub fn to_ebnf(&self) -> String
+        where T: Debug
+    {
+        let mut out = String::new();
+        for (name, node) in &self.rules {
+            writeln!(out, "{} ::= {} ;", name, ebnf(node, 0)).unwrap();
+        }
+        out
+    }
+
+    /// Draws the grammar as an SVG railroad diagram, with one diagram per rule.
+    pub fn to_svg(&self) -> String
+        where T: Debug
+    {
+        let mut body = String::new();
+        let mut height = MARGIN;
+        let mut width: usize = 0;
+        for (name, node) in &self.rules {
+            let block = railroad(node);
+            writeln!(body, r#"<text class="rule" x="{}" y="{}">{}</text>"#, MARGIN, height + 16, escape(name)).unwrap();
+            height += 24;
+            let (x, y) = (MARGIN, height);
+            writeln!(body, r#"<g transform="translate({},{})">"#, x, y).unwrap();
+            writeln!(body, r#"<path d="M0 {y}h{gap}M{end} {y}h{gap}"/>"#, y = block.y, gap = GAP, end = GAP + block.w).unwrap();
+            writeln!(body, r#"<g transform="translate({},0)">{}</g></g>"#, GAP, block.body).unwrap();
+            width = width.max(x + block.w + GAP * 2 + MARGIN);
+            height += block.h + MARGIN;
+        }
+        format!(
+            concat!(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#, "\n",
+                "<style>path {{ fill: none; stroke: black; stroke-width: 1.5 }} rect {{ fill: #eef; stroke: black; stroke-width: 1.5 }} ",
+                "text {{ font: 13px monospace; text-anchor: middle }} text.rule {{ font-weight: bold; text-anchor: start }}</style>\n",
+                "{body}</svg>\n",
+            ),
+            w = width,
+            h = height,
+            body = body,
+        )
+    }
+}
+
+// Writes `node` as EBNF, parenthesised if it binds more loosely than `prec` (0: choice, 1: sequence, 2: postfix)
+fn ebnf<T: Debug>(node: &Node<T>, prec: u8) -> String {
+    let list = |items: &[T]| items.iter().map(|item| format!("{:?}", item)).collect::<Vec<_>>();
+    let (s, own) = match node {
+        Node::Opaque => ("<?>".to_string(), 3),
+        Node::Any => ("<any>".to_string(), 3),
+        Node::End => ("<end>".to_string(), 3),
+        Node::Just(t) => (format!("{:?}", t), 3),
+        Node::Seq(ts) if ts.len() == 1 => (format!("{:?}", ts[0]), 3),
+        Node::Seq(ts) => (list(ts).join(" "), 1),
+        Node::OneOf(ts) => (list(ts).join(" | "), 0),
+        Node::NoneOf(ts) => (format!("[^ {} ]", list(ts).join(" ")), 3),
+        Node::Label(label) => (format!("<{}>", label), 3),
+        Node::Sequence(nodes) => (nodes.iter().map(|node| ebnf(node, 2)).collect::<Vec<_>>().join(" "), 1),
+        Node::Choice(nodes) => (nodes.iter().map(|node| ebnf(node, 1)).collect::<Vec<_>>().join(" | "), 0),
+        Node::Repeated(node) => (format!("{}*", ebnf(node, 3)), 2),
+        Node::OnceOrMore(node) => (format!("{}+", ebnf(node, 3)), 2),
+        Node::Optional(node) => (format!("{}?", ebnf(node, 3)), 2),
+        Node::SeparatedBy(item, sep) => (format!("({} ({} {})*)?", ebnf(item, 2), ebnf(sep, 2), ebnf(item, 2)), 2),
+        Node::Rule(name) => (name.clone(), 3),
+    };
+    if own < prec { format!("({})", s) } else { s }
+}
+
+// Railroad diagrams
+
+const MARGIN: usize = 16;
+const GAP: usize = 16;
+const ROW: usize = 28;
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// A piece of diagram with its own coordinates, entered and left at height `y` on its left and right edges
+struct Block {
+    w: usize,
+    h: usize,
+    y: usize,
+    body: String,
+}
+
+impl Block {
+    fn line() -> Self {
+        Self { w: 0, h: 0, y: 0, body: String::new() }
+    }
+
+    fn text(text: &str, rounded: bool) -> Self {
+        let w = text.chars().count() * 8 + 20;
+        let body = format!(
+            r#"<rect x="0" y="0" width="{w}" height="24" rx="{r}"/><text x="{x}" y="16">{t}</text>"#,
+            w = w,
+            r = if rounded { 12 } else { 0 },
+            x = w / 2,
+            t = escape(text),
+        );
+        Self { w, h: 24, y: 12, body }
+    }
+
+    fn placed(&self, x: usize, y: usize) -> String {
+        format!(r#"<g transform="translate({},{})">{}</g>"#, x, y, self.body)
+    }
+
+    fn sequence(blocks: Vec<Block>) -> Self {
+        let y = blocks.iter().map(|b| b.y).max().unwrap_or(0);
+        let below = blocks.iter().map(|b| b.h - b.y).max().unwrap_or(0);
+        let mut body = String::new();
+        let mut x = 0;
+        for (i, block) in blocks.iter().enumerate() {
+            if i > 0 {
+                write!(body, r#"<path d="M{} {}h{}"/>"#, x, y, GAP).unwrap();
+                x += GAP;
+            }
+            body += &block.placed(x, y - block.y);
+            x += block.w;
+        }
+        Self { w: x, h: y + below, y, body }
+    }
+
+    fn choice(blocks: Vec<Block>) -> Self {
+        let inner = blocks.iter().map(|b| b.w).max().unwrap_or(0);
+        let w = inner + GAP * 4;
+        let y = blocks.first().map_or(0, |b| b.y);
+        let mut body = String::new();
+        let mut top = 0;
+        for block in &blocks {
+            let by = top + block.y;
+            let x = GAP * 2 + (inner - block.w) / 2;
+            // The radius of the bends, if this branch is below the first
+            let r = if by > y { GAP / 2 } else { 0 };
+            // Branch off, run along to the block, then join back up
+            write!(
+                body,
+                r#"<path d="M0 {y}q{g} 0 {g} {r}V{v}q0 {r} {g} {r}H{x}M{x2} {by}H{w2}q{g} 0 {g} -{r}V{u}q0 -{r} {g} -{r}"/>"#,
+                y = y, by = by, g = GAP, x = x, x2 = x + block.w, w2 = w - GAP * 2, r = r,
+                v = by - r, u = y + r,
+            ).unwrap();
+            body += &block.placed(x, top);
+            top += block.h.max(ROW / 2) + 8;
+        }
+        Self { w, h: top - 8, y, body }
+    }
+
+    fn looped(block: Block, back: Option<Block>) -> Self {
+        let back = back.unwrap_or_else(Block::line);
+        let inner = block.w.max(back.w);
+        let w = inner + GAP * 2;
+        let low = block.h + 8 + back.y.max(4);
+        let mut body = String::new();
+        write!(body, r#"<path d="M0 {y}H{x}M{x2} {y}H{w}"/>"#, y = block.y, x = GAP + (inner - block.w) / 2, x2 = GAP + (inner + block.w) / 2, w = w).unwrap();
+        // The path back from the end to the start, passing through `back`
+        write!(
+            body,
+            r#"<path d="M{e} {y}q{g} 0 {g} {g}V{l}H{bx2}M{bx} {l}H{g}q-{g} 0 -{g} -{g}V{y}"/>"#,
+            e = w - GAP, y = block.y, g = GAP, l = low, bx = GAP + (inner - back.w) / 2, bx2 = GAP + (inner + back.w) / 2,
+        ).unwrap();
+        body += &block.placed(GAP + (inner - block.w) / 2, 0);
+        body += &back.placed(GAP + (inner - back.w) / 2, low - back.y);
+        Self { w, h: low + back.h - back.y + 4, y: block.y, body }
+    }
+}
+
+fn railroad<T: Debug>(node: &Node<T>) -> Block {
+    match node {
+        Node::Opaque => Block::text("?", false),
+        Node::Any => Block::text("any", false),
+        Node::End => Block::text("end", false),
+        Node::Just(t) => Block::text(&format!("{:?}", t), true),
+        Node::Seq(ts) => Block::text(&ts.iter().map(|t| format!("{:?}", t)).collect::<Vec<_>>().join(" "), true),
+        Node::OneOf(ts) => Block::choice(ts.iter().map(|t| Block::text(&format!("{:?}", t), true)).collect()),
+        Node::NoneOf(ts) => Block::text(&format!("none of {}", ts.iter().map(|t| format!("{:?}", t)).collect::<Vec<_>>().join(" ")), false),
+        Node::Label(label) => Block::text(label, false),
+        Node::Sequence(nodes) => Block::sequence(nodes.iter().map(railroad).collect()),
+        Node::Choice(nodes) => Block::choice(nodes.iter().map(railroad).collect()),
+        Node::Repeated(node) => Block::choice(vec![Block::line(), Block::looped(railroad(node), None)]),
+        Node::OnceOrMore(node) => Block::looped(railroad(node), None),
+        Node::Optional(node) => Block::choice(vec![Block::line(), railroad(node)]),
+        Node::SeparatedBy(item, sep) => Block::choice(vec![Block::line(), Block::looped(railroad(item), Some(railroad(sep)))]),
+        Node::Rule(name) => Block::text(name, false),
+    }
+}
+
+impl<P, E> Parser<P, E> {
+    /// Describes this parser as a grammar, whose first rule is this parser.
+    ///
+    /// The first rule is named `name`, unless this parser is a declaration, in which case it keeps the declaration's
+    /// own name (see `Declaration::named`).
+    pub fn grammar(&self, name: &str) -> Grammar<Thing<E, P::Input>>
+        where
+            P: Pattern<E>,
+            E: Error<P::Input>,
+    {
+        let mut grammar = Grammar::default();
+        let node = self.pat.describe(&mut grammar);
+        // A parser that is itself a declaration already has a rule
+        if !matches!(node, Node::Rule(_)) {
+            grammar.rules.insert(0, (name.to_string(), node));
+        }
+        grammar
+    }
+}
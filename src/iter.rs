@@ -0,0 +1,168 @@
+use std::iter::Fuse;
+use crate::{
+    Parser,
+    Pattern,
+    Error,
+    Stream,
+    memo,
+    profile,
+};
+
+// The fewest inputs pulled from the source at a time
+const CHUNK: usize = 256;
+
+/// The inputs pulled from a source that have not been consumed yet.
+pub(crate) struct Window<S: Iterator> {
+    source: Fuse<S>,
+    items: Vec<S::Item>,
+    // The number of inputs consumed so far
+    pos: usize,
+    // Whether the source has run out
+    exhausted: bool,
+}
+
+impl<S: Iterator> Window<S> {
+    pub(crate) fn new(source: S) -> Self {
+        Self { source: source.fuse(), items: Vec::new(), pos: 0, exhausted: false }
+    }
+
+    pub(crate) fn items(&self) -> &[S::Item] {
+        &self.items
+    }
+
+    /// Whether more inputs might be pulled after those in the window.
+    pub(crate) fn has_more(&self) -> bool {
+        !self.exhausted
+    }
+
+    /// Pulls more inputs into the window, at least doubling it, returning whether there were any.
+    pub(crate) fn refill(&mut self) -> bool {
+        let len = self.items.len();
+        let wanted = len.max(CHUNK);
+        self.items.extend(self.source.by_ref().take(wanted));
+        self.exhausted = self.items.len() < len + wanted;
+        self.items.len() > len
+    }
+
+    /// Drops the first `n` inputs of the window, which have been consumed.
+    pub(crate) fn consume(&mut self, n: usize) {
+        self.items.drain(..n);
+        self.pos += n;
+    }
+}
+
+/// An iterator that parses one item at a time. See `Parser::parse_iter`.
+pub struct ParseIter<'a, P, E, S: Iterator, F = fn(&<S as Iterator>::Item) -> bool> {
+    parser: &'a Parser<P, E>,
+    window: Window<S>,
+    resync: Option<F>,
+    done: bool,
+}
+
+impl<'a, P, E, S: Iterator, F> ParseIter<'a, P, E, S, F> {
+    /// After an error, skips past the next input for which `f` holds (such as a newline) and carries on parsing.
+    ///
+    /// Without this, iteration ends after the first error.
+    pub fn resync_after<G: Fn(&S::Item) -> bool>(self, f: G) -> ParseIter<'a, P, E, S, G> {
+        ParseIter {
+            parser: self.parser,
+            window: self.window,
+            resync: Some(f),
+            done: self.done,
+        }
+    }
+
+    /// The number of inputs consumed so far.
+    pub fn position(&self) -> usize {
+        self.window.pos
+    }
+}
+
+impl<'a, P, E, S, F> Iterator for ParseIter<'a, P, E, S, F>
+    where
+        P: Pattern<E, Input=S::Item>,
+        E: Error<S::Item>,
+        S: Iterator,
+        F: Fn(&S::Item) -> bool,
+{
+    type Item = Result<P::Output, Vec<E>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || (self.window.items.is_empty() && !self.window.refill()) {
+            return None;
+        }
+
+        // Each item is a parse of its own, however many times it is retried with more input
+        let (result, consumed) = profile::session(|| loop {
+            let mut stream = Stream::with_offset(&self.window.items, self.window.pos);
+            let start = stream;
+            let result = memo::session(|| {
+                stream = start;
+                self.parser.pat.parse(&mut stream)
+            });
+            let consumed = stream.checkpoint() as usize;
+            // If the parse reached the end of the window, more input might change its outcome
+            let len = self.window.items.len();
+            let reached_end = match &result {
+                Ok((_, fail)) => consumed == len || fail.furthest().is_some_and(|pos| pos.0 as usize >= len),
+                Err(fail) => fail.furthest().is_some_and(|pos| pos.0 as usize >= len),
+            };
+            if !reached_end || !self.window.refill() {
+                break (result, consumed);
+            }
+        });
+
+        match result {
+            Ok((out, _)) => {
+                // An item that consumes nothing would be produced forever
+                self.done = consumed == 0;
+                self.window.consume(consumed);
+                Some(Ok(out))
+            },
+            Err(fail) => {
+                match self.resync.take() {
+                    Some(f) => {
+                        let mut searched = 0;
+                        loop {
+                            if let Some(n) = self.window.items[searched..].iter().position(&f) {
+                                self.window.consume(searched + n + 1);
+                                break;
+                            }
+                            searched = self.window.items.len();
+                            if !self.window.refill() {
+                                self.done = true;
+                                break;
+                            }
+                        }
+                        self.resync = Some(f);
+                    },
+                    None => self.done = true,
+                }
+                Some(Err(fail.collect()))
+            },
+        }
+    }
+}
+
+impl<P, E> Parser<P, E> {
+    /// Parses `inputs` as a sequence of items, producing each one as it is parsed.
+    ///
+    /// Inputs are pulled from `inputs` only as they are needed, and dropped once the item they belong to has been
+    /// produced, so items can be parsed from a large file without reading all of it first. An item is parsed again with
+    /// more input whenever it reaches the end of the inputs pulled so far.
+    ///
+    /// Iteration ends at the end of the input, or after the first error unless `ParseIter::resync_after` is used.
+    pub fn parse_iter<I>(&self, inputs: I) -> ParseIter<'_, P, E, I::IntoIter>
+        where
+            P: Pattern<E>,
+            I: IntoIterator<Item=P::Input>,
+            E: Error<P::Input>,
+    {
+        ParseIter {
+            parser: self,
+            window: Window::new(inputs.into_iter()),
+            resync: None,
+            done: false,
+        }
+    }
+}
@@ -1,19 +1,40 @@
-#![feature(trait_alias)]
-
 pub mod error;
 pub mod stream;
 pub mod span;
 pub mod primitives;
 pub mod ops;
 pub mod declare;
+pub mod text;
+pub mod binary;
+pub mod tree;
+pub mod cst;
+pub mod source;
+pub mod iter;
+pub mod memo;
+pub mod grammar;
+pub mod trace;
+pub mod profile;
+pub mod generate;
+pub mod bidi;
+pub mod sax;
+#[cfg(feature = "regex")]
+pub mod regex;
+#[cfg(feature = "macros")]
+pub mod macros;
+#[cfg(feature = "derive")]
+pub mod parse;
 mod fail;
 mod util;
 
-use std::marker::PhantomData;
+use std::{
+    marker::PhantomData,
+    ops::Range,
+};
 use crate::{
     error::*,
     stream::*,
     fail::*,
+    grammar::{Grammar, Node, Thing},
 };
 
 pub type Index = u64;
@@ -28,6 +49,13 @@ pub trait Pattern<E> {
     fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E>;
 
     fn cloned(&self) -> Self where Self: Sized;
+
+    /// Describes the structure of this pattern. See the `grammar` module.
+    fn describe(&self, _grammar: &mut Grammar<Thing<E, Self::Input>>) -> Node<Thing<E, Self::Input>>
+        where E: Error<Self::Input>
+    {
+        Node::Opaque
+    }
 }
 
 pub struct Parser<P, E> {
@@ -57,7 +85,15 @@ impl<P, E> Parser<P, E> {
             P: Pattern<E>,
             E: Error<P::Input>,
     {
-        self.pat.parse(&mut Stream::from(inputs))
+        self.parse_stream(&mut Stream::from(inputs))
+    }
+
+    fn parse_stream(&self, stream: &mut Stream<P::Input>) -> ParseResult<P::Output, E>
+        where
+            P: Pattern<E>,
+            E: Error<P::Input>,
+    {
+        run(&self.pat, stream)
     }
 
     pub fn parse<I>(&self, inputs: I) -> Result<P::Output, Vec<E>>
@@ -74,6 +110,103 @@ impl<P, E> Parser<P, E> {
         }
     }
 
+    /// Parses the start of `inputs`, returning the output along with the number of inputs consumed.
+    ///
+    /// Any inputs after those consumed are left for something else to handle.
+    pub fn parse_prefix<I>(&self, inputs: I) -> Result<(P::Output, usize), Vec<E>>
+        where
+            P: Pattern<E>,
+            I: IntoIterator<Item=P::Input>,
+            E: Error<P::Input>,
+    {
+        let inputs = inputs.into_iter().collect::<Vec<_>>();
+        let mut stream = Stream::from(&inputs[..]);
+        match self.parse_stream(&mut stream) {
+            Ok((out, _)) => Ok((out, stream.checkpoint() as usize)),
+            Err(fail) => Err(fail.collect()),
+        }
+    }
+
+    /// Parses `inputs`, failing unless all of them are consumed.
+    pub fn parse_complete<I>(&self, inputs: I) -> Result<P::Output, Vec<E>>
+        where
+            P: Pattern<E>,
+            I: IntoIterator<Item=P::Input>,
+            E: Error<P::Input>,
+    {
+        // The end is checked as part of the parse, so that a failure there counts as the parse failing
+        struct Complete<'a, A>(&'a A);
+
+        impl<'a, A, E> Pattern<E> for Complete<'a, A>
+            where
+                A: Pattern<E>,
+                E: Error<A::Input>,
+        {
+            type Input = A::Input;
+            type Output = A::Output;
+
+            fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+                let (out, fail) = self.0.parse(stream)?;
+                let checkpoint = stream.checkpoint();
+                match stream.next() {
+                    Some((idx, sym)) => Err(fail.max(Fail::one(idx, E::expected_end(sym, stream.span_from(checkpoint))))),
+                    None => Ok((out, fail)),
+                }
+            }
+
+            fn cloned(&self) -> Self where Self: Sized {
+                Self(self.0)
+            }
+        }
+
+        let inputs = inputs.into_iter().collect::<Vec<_>>();
+        match run(&Complete(&self.pat), &mut Stream::from(&inputs[..])) {
+            Ok((out, _)) => Ok(out),
+            Err(fail) => Err(fail.collect()),
+        }
+    }
+
+    /// Parses items that each carry a span in some earlier input, such as tokens produced by a lexer.
+    ///
+    /// Spans seen by the parser (in errors and `map_with_span`, for example) cover the original input rather than
+    /// the items. An item without a span is treated as empty, sitting at the end of the item before it.
+    pub fn parse_spanned<I, S>(&self, inputs: I) -> Result<P::Output, Vec<E>>
+        where
+            P: Pattern<E>,
+            I: IntoIterator<Item=(P::Input, S)>,
+            S: Into<Option<Range<usize>>>,
+            E: Error<P::Input>,
+    {
+        let mut items = Vec::new();
+        let mut spans = Vec::<Range<usize>>::new();
+        for (item, span) in inputs {
+            let end = spans.last().map_or(0, |span| span.end);
+            items.push(item);
+            spans.push(span.into().unwrap_or(end..end));
+        }
+        match self.parse_stream(&mut Stream::with_spans(&items, &spans)) {
+            Ok((out, _)) => Ok(out),
+            Err(fail) => Err(fail.collect()),
+        }
+    }
+
+    /// Splits `inputs` into tokens with `lexer`, then parses the tokens with this parser.
+    ///
+    /// `lexer` produces each token alongside its span, usually via `map_with_span`. See `parse_spanned`.
+    pub fn parse_lexed<L, D, I, S>(&self, lexer: &Parser<L, D>, inputs: I) -> Result<P::Output, PipelineError<D, E>>
+        where
+            P: Pattern<E>,
+            L: Pattern<D, Output=Vec<(P::Input, S)>>,
+            I: IntoIterator<Item=L::Input>,
+            I::IntoIter: Clone,
+            S: Into<Option<Range<usize>>>,
+            D: Error<L::Input>,
+            E: Error<P::Input>,
+    {
+        let tokens = lexer.parse(inputs).map_err(PipelineError::Lex)?;
+        self.parse_spanned(tokens).map_err(PipelineError::Parse)
+    }
+
     pub fn boxed(self) -> Parser<impl Pattern<E, Input=P::Input, Output=P::Output>, E>
         where
             P: Pattern<E> + 'static,
@@ -111,12 +244,30 @@ impl<P, E> Parser<P, E> {
             fn cloned(&self) -> Self where Self: Sized {
                 Self(self.0.cloned())
             }
+
+            fn describe(&self, grammar: &mut Grammar<Thing<E, I>>) -> Node<Thing<E, I>>
+                where E: Error<I>
+            {
+                self.0.pat().describe(grammar)
+            }
         }
 
         Parser::from_pat(Boxed(Box::new(BoxedPattern(self.pat))))
     }
 }
 
+// Runs a whole parse of `pat` over `stream`. Any profiles used are finished once it is done, and a failed parse that
+// reused memoized outputs is run again without them (see `profile` and `memo`).
+fn run<A, E>(pat: &A, stream: &mut Stream<A::Input>) -> ParseResult<A::Output, E>
+    where A: Pattern<E>,
+{
+    let start = *stream;
+    profile::session(|| memo::session(|| {
+        *stream = start;
+        pat.parse(stream)
+    }))
+}
+
 pub mod prelude {
     pub use crate::{
         Parser,
@@ -125,5 +276,13 @@ pub mod prelude {
         primitives::*,
         declare::*,
         error::DefaultError,
+        span::Spanned,
+        select,
     };
+    #[cfg(feature = "regex")]
+    pub use crate::regex::regex;
+    #[cfg(feature = "macros")]
+    pub use crate::parsers;
+    #[cfg(feature = "derive")]
+    pub use crate::parse::Parse;
 }
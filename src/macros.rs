@@ -0,0 +1,235 @@
+//! Support for the `parsers!` grammar DSL.
+//!
+//! ```ignore
+//! parsers! {
+//!     digits = { { one_of(b"0123456789") }+ }
+//!     list: Vec<Vec<u8>> = { b'['~ -& (digits ... b','~) &- b']' }
+//! }
+//! ```
+//!
+//! Each rule is declared before any rule is defined, so rules may refer to each other (and themselves) freely. A rule
+//! may be given an output type annotation to help type inference. Rule bodies are made up of:
+//!
+//! | Syntax         | Meaning                                                                  |
+//! |----------------|--------------------------------------------------------------------------|
+//! | `b'x'`, `'x'`  | `just` the given symbol                                                  |
+//! | `b"xy"`, `"xy"`| `seq` of the given symbols                                               |
+//! | `rule`         | A reference to another rule                                              |
+//! | `{ expr }`     | An arbitrary Rust expression evaluating to a `Parser`                    |
+//! | `( ... )`      | Grouping                                                                 |
+//! | `a*` `a+` `a?` | `repeated`, `once_or_more`, `or_not`                                     |
+//! | `a.%`          | `chained`: wrap the output in a `Vec`                                    |
+//! | `a.#`          | Flatten a nested output, such as `Option<Vec<T>>`, into a `Vec<T>`       |
+//! | `~a`, `a~`     | Allow whitespace before or after `a`                                     |
+//! | `a % b`        | Concatenate the outputs of `a` and `b` (anything iterable) into a `Vec`  |
+//! | `a & b`        | `then`                                                                   |
+//! | `a -& b`       | `padding_for`: keep only the output of `b`                               |
+//! | `a &- b`       | `padded_by`: keep only the output of `a`                                 |
+//! | `a ... b`      | `separated_by`                                                           |
+//! | `a -> v`       | `to`: replace the output with `v`                                        |
+//! | `a => f`       | `map`                                                                    |
+//! | `a \| b`       | `or`                                                                     |
+//!
+//! Postfix operators bind tightest, followed by the binary operators (all left-associative with equal precedence),
+//! then `->` and `=>`, then `|`.
+
+use crate::{
+    primitives::{just, seq},
+    Parser,
+    Pattern,
+    Error,
+};
+
+/// A literal that can appear in a `parsers!` rule.
+pub trait Token<I, E> {
+    type Output;
+
+    fn parser(self) -> Parser<impl Pattern<E, Input=I, Output=Self::Output> + 'static, E>;
+}
+
+impl<E: Error<u8> + 'static> Token<u8, E> for u8 {
+    type Output = u8;
+
+    fn parser(self) -> Parser<impl Pattern<E, Input=u8, Output=u8> + 'static, E> {
+        just(self)
+    }
+}
+
+impl<E: Error<char> + 'static> Token<char, E> for char {
+    type Output = char;
+
+    fn parser(self) -> Parser<impl Pattern<E, Input=char, Output=char> + 'static, E> {
+        just(self)
+    }
+}
+
+impl<E: Error<u8> + 'static, const N: usize> Token<u8, E> for &'static [u8; N] {
+    type Output = Vec<u8>;
+
+    fn parser(self) -> Parser<impl Pattern<E, Input=u8, Output=Vec<u8>> + 'static, E> {
+        seq(self.to_vec())
+    }
+}
+
+impl<E: Error<char> + 'static> Token<char, E> for &'static str {
+    type Output = Vec<char>;
+
+    fn parser(self) -> Parser<impl Pattern<E, Input=char, Output=Vec<char>> + 'static, E> {
+        seq(self.chars().collect::<Vec<_>>())
+    }
+}
+
+#[doc(hidden)]
+pub fn token<I, E, T: Token<I, E>>(token: T) -> Parser<impl Pattern<E, Input=I, Output=T::Output> + 'static, E> {
+    token.parser()
+}
+
+#[doc(hidden)]
+pub fn concat<P, Q, T, E>(a: Parser<P, E>, b: Parser<Q, E>) -> Parser<impl Pattern<E, Input=P::Input, Output=Vec<T>>, E>
+    where
+        P: Pattern<E>,
+        Q: Pattern<E, Input=P::Input>,
+        P::Output: IntoIterator<Item=T>,
+        Q::Output: IntoIterator<Item=T>,
+        E: Error<P::Input>,
+{
+    a.then(b).map(|(a, b)| a.into_iter().chain(b).collect())
+}
+
+#[doc(hidden)]
+pub fn flatten<P, T, E>(a: Parser<P, E>) -> Parser<impl Pattern<E, Input=P::Input, Output=Vec<T>>, E>
+    where
+        P: Pattern<E>,
+        P::Output: IntoIterator,
+        <P::Output as IntoIterator>::Item: IntoIterator<Item=T>,
+        E: Error<P::Input>,
+{
+    a.map(|a| a.into_iter().flatten().collect())
+}
+
+/// Define a set of mutually recursive rules using the grammar DSL. See the `macros` module for the syntax.
+///
+/// Each rule becomes a local variable holding its parser.
+#[macro_export]
+macro_rules! parsers {
+    ($($name:ident $(: $ty:ty)? = { $($body:tt)* })*) => {
+        #[allow(unused_variables)]
+        let ($($name,)*) = ($($crate::declare::declare::<_, _, $crate::parsers!(@ty $($ty)?)>().named(stringify!($name)),)*);
+        #[allow(unused_variables)]
+        let ($($name,)*) = ($($name.define_shared($crate::parsers!(@alt [] [] $($body)*)),)*);
+    };
+
+    (@ty) => { _ };
+    (@ty $ty:ty) => { $ty };
+
+    // Alternatives, separated by `|`
+
+    (@alt [$($alts:tt)*] [$($cur:tt)*] | $($rest:tt)*) => {
+        $crate::parsers!(@alt [$($alts)* [$($cur)*]] [] $($rest)*)
+    };
+    (@alt [$($alts:tt)*] [$($cur:tt)*] $t:tt $($rest:tt)*) => {
+        $crate::parsers!(@alt [$($alts)*] [$($cur)* $t] $($rest)*)
+    };
+    (@alt [$($alts:tt)*] [$($cur:tt)*]) => {
+        $crate::parsers!(@or [] $($alts)* [$($cur)*])
+    };
+
+    (@or [$($acc:tt)*] [] $($rest:tt)*) => {
+        $crate::parsers!(@or [$($acc)*] $($rest)*)
+    };
+    (@or [] [$($alt:tt)+] $($rest:tt)*) => {
+        $crate::parsers!(@or [($crate::parsers!(@map [] $($alt)+))] $($rest)*)
+    };
+    (@or [$($acc:tt)+] [$($alt:tt)+] $($rest:tt)*) => {
+        $crate::parsers!(@or [($($acc)+.or($crate::parsers!(@map [] $($alt)+)))] $($rest)*)
+    };
+    (@or [$($acc:tt)+]) => { $($acc)+ };
+    (@or []) => { compile_error!("expected a parser") };
+
+    // Output mapping with `=>` and `->`
+
+    (@map [$($cur:tt)*] => $($rest:tt)*) => {
+        $crate::parsers!(@mapped [$crate::parsers!(@seq [] [first] $($cur)*)] => $($rest)*)
+    };
+    (@map [$($cur:tt)*] -> $($rest:tt)*) => {
+        $crate::parsers!(@mapped [$crate::parsers!(@seq [] [first] $($cur)*)] -> $($rest)*)
+    };
+    (@map [$($cur:tt)*] $t:tt $($rest:tt)*) => {
+        $crate::parsers!(@map [$($cur)* $t] $($rest)*)
+    };
+    (@map [$($cur:tt)*]) => {
+        $crate::parsers!(@seq [] [first] $($cur)*)
+    };
+
+    (@mapped [$($p:tt)*] => $f:tt $($rest:tt)*) => {
+        $crate::parsers!(@mapped [($($p)*).map($f)] $($rest)*)
+    };
+    (@mapped [$($p:tt)*] -> $v:tt $($rest:tt)*) => {
+        $crate::parsers!(@mapped [($($p)*).to($v)] $($rest)*)
+    };
+    (@mapped [$($p:tt)*]) => { $($p)* };
+    (@mapped [$($p:tt)*] $($rest:tt)+) => {
+        compile_error!(concat!("expected `=>` or `->`, found `", stringify!($($rest)+), "`"))
+    };
+
+    // Operands, each with an optional `~` prefix
+
+    (@seq [$($acc:tt)*] [$op:ident] ~ $atom:tt $($rest:tt)*) => {
+        $crate::parsers!(@post [$($acc)*] [$op] [$crate::text::whitespace().padding_for($crate::parsers!(@atom $atom))] $($rest)*)
+    };
+    (@seq [$($acc:tt)*] [$op:ident] $atom:tt $($rest:tt)*) => {
+        $crate::parsers!(@post [$($acc)*] [$op] [$crate::parsers!(@atom $atom)] $($rest)*)
+    };
+    (@seq [$($acc:tt)*] [$op:ident]) => {
+        compile_error!("expected an operand")
+    };
+
+    (@atom { $($e:tt)* }) => { ($($e)*) };
+    (@atom ( $($e:tt)* )) => { $crate::parsers!(@alt [] [] $($e)*) };
+    (@atom $l:literal) => { $crate::macros::token($l) };
+    (@atom $rule:ident) => { $rule.link() };
+    (@atom $t:tt) => { compile_error!(concat!("unexpected `", stringify!($t), "`")) };
+
+    // Postfix operators
+
+    (@post [$($acc:tt)*] [$op:ident] [$($p:tt)*] * $($rest:tt)*) => {
+        $crate::parsers!(@post [$($acc)*] [$op] [($($p)*).repeated()] $($rest)*)
+    };
+    (@post [$($acc:tt)*] [$op:ident] [$($p:tt)*] + $($rest:tt)*) => {
+        $crate::parsers!(@post [$($acc)*] [$op] [($($p)*).once_or_more()] $($rest)*)
+    };
+    (@post [$($acc:tt)*] [$op:ident] [$($p:tt)*] ? $($rest:tt)*) => {
+        $crate::parsers!(@post [$($acc)*] [$op] [($($p)*).or_not()] $($rest)*)
+    };
+    (@post [$($acc:tt)*] [$op:ident] [$($p:tt)*] . % $($rest:tt)*) => {
+        $crate::parsers!(@post [$($acc)*] [$op] [($($p)*).chained()] $($rest)*)
+    };
+    (@post [$($acc:tt)*] [$op:ident] [$($p:tt)*] . # $($rest:tt)*) => {
+        $crate::parsers!(@post [$($acc)*] [$op] [$crate::macros::flatten($($p)*)] $($rest)*)
+    };
+    (@post [$($acc:tt)*] [$op:ident] [$($p:tt)*] ~ $($rest:tt)*) => {
+        $crate::parsers!(@post [$($acc)*] [$op] [($($p)*).padded_by($crate::text::whitespace())] $($rest)*)
+    };
+    (@post [$($acc:tt)*] [$op:ident] [$($p:tt)*] $($rest:tt)*) => {
+        $crate::parsers!(@bin [$crate::parsers!(@combine [$($acc)*] [$op] [$($p)*])] $($rest)*)
+    };
+
+    (@combine [] [first] [$($p:tt)*]) => { $($p)* };
+    (@combine [$($acc:tt)*] [concat] [$($p:tt)*]) => { $crate::macros::concat($($acc)*, $($p)*) };
+    (@combine [$($acc:tt)*] [then] [$($p:tt)*]) => { ($($acc)*).then($($p)*) };
+    (@combine [$($acc:tt)*] [padding_for] [$($p:tt)*]) => { ($($acc)*).padding_for($($p)*) };
+    (@combine [$($acc:tt)*] [padded_by] [$($p:tt)*]) => { ($($acc)*).padded_by($($p)*) };
+    (@combine [$($acc:tt)*] [separated_by] [$($p:tt)*]) => { ($($acc)*).separated_by($($p)*) };
+
+    // Binary operators
+
+    (@bin [$($acc:tt)*] % $($rest:tt)*) => { $crate::parsers!(@seq [$($acc)*] [concat] $($rest)*) };
+    (@bin [$($acc:tt)*] - & $($rest:tt)*) => { $crate::parsers!(@seq [$($acc)*] [padding_for] $($rest)*) };
+    (@bin [$($acc:tt)*] & - $($rest:tt)*) => { $crate::parsers!(@seq [$($acc)*] [padded_by] $($rest)*) };
+    (@bin [$($acc:tt)*] & $($rest:tt)*) => { $crate::parsers!(@seq [$($acc)*] [then] $($rest)*) };
+    (@bin [$($acc:tt)*] ... $($rest:tt)*) => { $crate::parsers!(@seq [$($acc)*] [separated_by] $($rest)*) };
+    (@bin [$($acc:tt)*]) => { $($acc)* };
+    (@bin [$($acc:tt)*] $($rest:tt)+) => {
+        compile_error!(concat!("expected an operator, found `", stringify!($($rest)+), "`"))
+    };
+}
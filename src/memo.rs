@@ -0,0 +1,224 @@
+//! Memoization of parser results, allowing input to be reparsed incrementally after an edit.
+//!
+//! ```ignore
+//! let memo = Memo::new();
+//! let item = item_parser.memoized(&memo);
+//! let file = item.repeated();
+//!
+//! file.parse(text.chars());
+//! // Replace the 3 characters at offset 10 with 5 new ones
+//! text.replace_range(10..13, "hello");
+//! memo.edit(10..13, 5);
+//! file.parse(text.chars()); // Only the items that overlap the edit are parsed again
+//! ```
+//!
+//! Reused outputs bring along the syntax tree nodes (see `cst`) that were built when they were first parsed, but not
+//! what they expected beyond the inputs they consumed. So that errors are the same as those of a parse from scratch, a
+//! parse that fails after reusing outputs is run again without reusing any.
+//!
+//! Outputs are only remembered for the top-level input: parsers within a token tree group or a nested parse, or
+//! passing events on to a `sax::Sink`, always run in full.
+
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    marker::PhantomData,
+    ops::Range,
+    rc::Rc,
+};
+use crate::{
+    Parser,
+    Pattern,
+    Error,
+    Stream,
+    ParseResult,
+    Fail,
+    cst::Event,
+    grammar::{Grammar, Node},
+};
+
+struct Entry {
+    // The offset just past the inputs consumed
+    end: usize,
+    output: Box<dyn Any>,
+    // Whether the parse passed a cut
+    cut: bool,
+    // The syntax tree events written, along with their positions relative to the offset
+    events: Vec<(Event, usize)>,
+}
+
+#[derive(Default)]
+struct Session {
+    // Whether any outputs have been reused
+    reused: bool,
+    // Whether outputs may not be reused, since the parse is being run again
+    exact: bool,
+}
+
+thread_local! {
+    // The state of each parse underway, innermost last
+    static SESSIONS: RefCell<Vec<Session>> = const { RefCell::new(Vec::new()) };
+}
+
+// Runs a whole parse with `f`, running it again without reusing outputs if it fails after reusing some. `f` must start
+// from the beginning each time.
+pub(crate) fn session<O, E>(mut f: impl FnMut() -> ParseResult<O, E>) -> ParseResult<O, E> {
+    SESSIONS.with(|sessions| sessions.borrow_mut().push(Session::default()));
+    let mut result = f();
+    let retry = result.is_err() && SESSIONS.with(|sessions| sessions.borrow().last().is_some_and(|session| session.reused));
+    if retry {
+        SESSIONS.with(|sessions| sessions.borrow_mut().last_mut().unwrap().exact = true);
+        result = f();
+    }
+    SESSIONS.with(|sessions| sessions.borrow_mut().pop());
+    result
+}
+
+// Whether the parse underway may reuse outputs, noting that it has if `reused`
+fn may_reuse(reused: bool) -> bool {
+    SESSIONS.with(|sessions| match sessions.borrow_mut().last_mut() {
+        Some(session) if session.exact => false,
+        Some(session) => {
+            session.reused |= reused;
+            true
+        },
+        None => true,
+    })
+}
+
+#[derive(Default)]
+struct Table {
+    next_rule: Cell<u32>,
+    entries: RefCell<HashMap<(u32, usize), Entry>>,
+}
+
+/// A table of memoized parser outputs, keyed by parser and offset. Cloning a `Memo` shares its table.
+///
+/// The table assumes that every parse sees the same input, other than the changes reported with `edit`.
+#[derive(Clone, Default)]
+pub struct Memo(Rc<Table>);
+
+impl Memo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reports that the inputs in `range` were replaced with `len` new inputs.
+    ///
+    /// Entries whose consumed inputs overlap or touch the edited range are discarded, and those after it are moved
+    /// to their new offsets.
+    pub fn edit(&self, range: Range<usize>, len: usize) {
+        let mut entries = self.0.entries.borrow_mut();
+        *entries = entries
+            .drain()
+            .filter(|((_, offset), entry)| entry.end < range.start || *offset > range.end)
+            .map(|((rule, offset), entry)| if offset > range.end {
+                let moved = |pos: usize| pos - range.end + range.start + len;
+                ((rule, moved(offset)), Entry { end: moved(entry.end), ..entry })
+            } else {
+                ((rule, offset), entry)
+            })
+            .collect();
+    }
+
+    /// Discards every entry.
+    pub fn clear(&self) {
+        self.0.entries.borrow_mut().clear();
+    }
+
+    /// The number of memoized outputs.
+    pub fn len(&self) -> usize {
+        self.0.entries.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn new_rule(&self) -> u32 {
+        let rule = self.0.next_rule.get();
+        self.0.next_rule.set(rule + 1);
+        rule
+    }
+}
+
+// Memoized
+
+impl<P, E> Parser<P, E> {
+    /// Remembers the output of this parser at each offset in `memo`, reusing it when parsing the same offset again.
+    ///
+    /// Only successful parses are remembered, along with the syntax tree nodes they built. See the module
+    /// documentation for how errors are kept the same and where outputs are not remembered.
+    ///
+    /// An output is reused as long as no edit touches the inputs it was parsed from, so this parser should not look
+    /// more than one input beyond those it consumes.
+    pub fn memoized(self, memo: &Memo) -> Parser<impl Pattern<E, Input=P::Input, Output=P::Output>, E>
+        where
+            P: Pattern<E>,
+            P::Output: Clone + 'static,
+            E: Error<P::Input>,
+    {
+        struct Memoized<A, E>(A, u32, Memo, PhantomData<E>);
+
+        impl<A, E> Pattern<E> for Memoized<A, E>
+            where
+                A: Pattern<E>,
+                A::Output: Clone + 'static,
+                E: Error<A::Input>,
+        {
+            type Input = A::Input;
+            type Output = A::Output;
+
+            fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+                // Positions within nested streams do not say where in the input they are, and emitted values cannot
+                // be replayed
+                if stream.is_nested() || stream.is_streaming() {
+                    return self.0.parse(stream);
+                }
+
+                let offset = stream.input_pos();
+                let start = stream.checkpoint() as usize;
+                if may_reuse(false) {
+                    let entries = self.2.0.entries.borrow();
+                    let hit = entries.get(&(self.1, offset)).and_then(|entry| {
+                        Some((entry, entry.output.downcast_ref::<A::Output>()?.clone()))
+                    });
+                    if let Some((entry, out)) = hit {
+                        let mut after = *stream;
+                        if after.take_slice(entry.end - offset).is_some() {
+                            for (event, pos) in &entry.events {
+                                after.emit_at(event.copy().unwrap(), start + pos);
+                            }
+                            if entry.cut {
+                                after.cut();
+                            }
+                            *stream = after;
+                            may_reuse(true);
+                            return Ok((out, Fail::none()));
+                        }
+                    }
+                }
+
+                let was_cut = stream.is_cut();
+                let events_len = stream.events_len();
+                let (out, fail) = self.0.parse(stream)?;
+                let cut = stream.is_cut() && !was_cut;
+                let events = stream.events_since(events_len).into_iter().map(|(event, pos)| (event, pos - start)).collect();
+                let entry = Entry { end: stream.input_pos(), output: Box::new(out.clone()), cut, events };
+                self.2.0.entries.borrow_mut().insert((self.1, offset), entry);
+                Ok((out, fail))
+            }
+
+            fn cloned(&self) -> Self where Self: Sized {
+                Self(self.0.cloned(), self.1, self.2.clone(), PhantomData)
+            }
+
+            fn describe(&self, grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+                self.0.describe(grammar)
+            }
+        }
+
+        Parser::from_pat(Memoized(self.pat, memo.new_rule(), memo.clone(), PhantomData))
+    }
+}
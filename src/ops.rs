@@ -1,5 +1,7 @@
 use std::marker::PhantomData;
 use crate::{
+    span::Spanned,
+    grammar::{Grammar, Node},
     util::attempt,
     Parser,
     Pattern,
@@ -33,6 +35,10 @@ impl<P, E> Parser<P, E> {
             fn cloned(&self) -> Self where Self: Sized {
                 Self(self.0.cloned(), self.1.clone(), PhantomData)
             }
+
+            fn describe(&self, grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+                self.0.describe(grammar)
+            }
         }
 
         Parser::from_pat(Map(self.pat, f, PhantomData))
@@ -63,11 +69,25 @@ impl<P, E> Parser<P, E> {
             fn cloned(&self) -> Self where Self: Sized {
                 Self(self.0.cloned(), self.1.clone(), PhantomData)
             }
+
+            fn describe(&self, grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+                self.0.describe(grammar)
+            }
         }
 
         Parser::from_pat(MapWithRange(self.pat, f, PhantomData))
     }
 
+    /// Attaches the span of the input that this parser consumed to its output.
+    #[allow(clippy::type_complexity)]
+    pub fn spanned(self) -> Parser<impl Pattern<E, Input=P::Input, Output=Spanned<P::Output, E::Span>>, E>
+        where
+            P: Pattern<E>,
+            E: Error<P::Input>,
+    {
+        self.map_with_span(Spanned::new)
+    }
+
     pub fn chained(self) -> Parser<impl Pattern<E, Input=P::Input, Output=Vec<P::Output>>, E>
         where
             P: Pattern<E>,
@@ -81,7 +101,7 @@ impl<P, E> Parser<P, E> {
             P: Pattern<E, Output=(A, Vec<B>)>,
             E: Error<P::Input>,
     {
-        self.map(move |(init, items)| items.into_iter().fold(init, |a, b| f(a, b)))
+        self.map(move |(init, items)| items.into_iter().fold(init, &f))
     }
 
     pub fn reduce_right<A, B>(self, f: impl Fn(A, B) -> B + Clone) -> Parser<impl Pattern<E, Input=P::Input, Output=B>, E>
@@ -170,6 +190,10 @@ impl<P, E> Parser<P, E> {
             fn cloned(&self) -> Self where Self: Sized {
                 Self(self.0.cloned(), self.1.cloned(), PhantomData)
             }
+
+            fn describe(&self, grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+                self.0.describe(grammar).then(self.1.describe(grammar))
+            }
         }
 
         Parser::from_pat(Then(self.pat, other.pat, PhantomData))
@@ -203,8 +227,9 @@ impl<P, E> Parser<P, E> {
             type Output = X;
 
             fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
-                match self.0.parse(stream) {
+                match stream.alternative(|stream| self.0.parse(stream)) {
                     Ok((out, fail)) => Ok((out, fail)),
+                    Err(a_fail) if a_fail.is_committed() => Err(a_fail),
                     Err(a_fail) => match self.1.parse(stream) {
                         Ok((out, b_fail)) => Ok((out, a_fail.max(b_fail))),
                         Err(b_fail) => Err(a_fail.max(b_fail)),
@@ -215,6 +240,10 @@ impl<P, E> Parser<P, E> {
             fn cloned(&self) -> Self where Self: Sized {
                 Self(self.0.cloned(), self.1.cloned(), PhantomData)
             }
+
+            fn describe(&self, grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+                self.0.describe(grammar).or(self.1.describe(grammar))
+            }
         }
 
         Parser::from_pat(Or(self.pat, other.pat, PhantomData))
@@ -255,8 +284,9 @@ impl<P, E> Parser<P, E> {
                 let mut outputs = Vec::new();
 
                 loop {
-                    match self.0.parse(stream) {
+                    match stream.alternative(|stream| self.0.parse(stream)) {
                         Ok((out, _)) => outputs.push(out),
+                        Err(fail) if fail.is_committed() => break Err(fail),
                         Err(fail) => break Ok((outputs, fail)),
                     }
                 }
@@ -265,6 +295,10 @@ impl<P, E> Parser<P, E> {
             fn cloned(&self) -> Self where Self: Sized {
                 Self(self.0.cloned(), PhantomData)
             }
+
+            fn describe(&self, grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+                Node::Repeated(Box::new(self.0.describe(grammar)))
+            }
         }
 
         Parser::from_pat(Repeated(self.pat, PhantomData))
@@ -290,13 +324,16 @@ impl<P, E> Parser<P, E> {
                 let mut outputs = Vec::new();
 
                 for _ in 0.. {
-                    match self.0.parse(stream) {
+                    match stream.alternative(|stream| self.0.parse(stream)) {
                         Ok((out, _)) => outputs.push(out),
+                        Err(fail) if fail.is_committed() => return Err(fail),
                         Err(fail) => return Ok((outputs, fail)),
                     }
 
-                    if let Err(fail) = self.1.parse(stream) {
-                        return Ok((outputs, fail));
+                    match stream.alternative(|stream| self.1.parse(stream)) {
+                        Ok(_) => {},
+                        Err(fail) if fail.is_committed() => return Err(fail),
+                        Err(fail) => return Ok((outputs, fail)),
                     }
                 }
 
@@ -306,6 +343,10 @@ impl<P, E> Parser<P, E> {
             fn cloned(&self) -> Self where Self: Sized {
                 Self(self.0.cloned(), self.1.cloned(), PhantomData)
             }
+
+            fn describe(&self, grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+                Node::SeparatedBy(Box::new(self.0.describe(grammar)), Box::new(self.1.describe(grammar)))
+            }
         }
 
         Parser::from_pat(SeparatedBy(self.pat, other.pat, PhantomData))
@@ -330,9 +371,9 @@ impl<P, E> Parser<P, E> {
                 let mut outputs = Vec::new();
 
                 loop {
-                    match self.0.parse(stream) {
+                    match stream.alternative(|stream| self.0.parse(stream)) {
                         Ok((out, _)) => outputs.push(out),
-                        Err(fail) if outputs.len() > 0 => return Ok((outputs, fail)),
+                        Err(fail) if !outputs.is_empty() && !fail.is_committed() => return Ok((outputs, fail)),
                         Err(fail) => return Err(fail),
                     }
                 }
@@ -341,6 +382,10 @@ impl<P, E> Parser<P, E> {
             fn cloned(&self) -> Self where Self: Sized {
                 Self(self.0.cloned(), PhantomData)
             }
+
+            fn describe(&self, grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+                Node::OnceOrMore(Box::new(self.0.describe(grammar)))
+            }
         }
 
         Parser::from_pat(OnceOrMore(self.pat, PhantomData))
@@ -362,8 +407,9 @@ impl<P, E> Parser<P, E> {
             type Output = Option<X>;
 
             fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
-                match self.0.parse(stream) {
+                match stream.alternative(|stream| self.0.parse(stream)) {
                     Ok((out, fail)) => Ok((Some(out), fail)),
+                    Err(fail) if fail.is_committed() => Err(fail),
                     Err(fail) => Ok((None, fail)),
                 }
             }
@@ -371,8 +417,56 @@ impl<P, E> Parser<P, E> {
             fn cloned(&self) -> Self where Self: Sized {
                 Self(self.0.cloned(), PhantomData)
             }
+
+            fn describe(&self, grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+                Node::Optional(Box::new(self.0.describe(grammar)))
+            }
         }
 
         Parser::from_pat(OrNot(self.pat, PhantomData))
     }
+
+    /// Commits to the enclosing alternative once this parser succeeds: failing anywhere after it, until that
+    /// alternative ends, skips the alternatives that remain and reports the failure as it is.
+    ///
+    /// The enclosing alternative is the innermost branch of `or`, or item of `repeated`, `separated_by`,
+    /// `once_or_more` or `or_not`. Committed failures pass through any further alternatives, so they usually end the
+    /// parse.
+    ///
+    /// ```ignore
+    /// let if_stmt = keyword("if").cut().padding_for(expr).then(block);
+    /// let stmt = if_stmt.or(expr_stmt); // `if 1 +` reports the error in `expr`, rather than trying `expr_stmt`
+    /// ```
+    pub fn cut(self) -> Parser<impl Pattern<E, Input=P::Input, Output=P::Output>, E>
+        where
+            P: Pattern<E>,
+            E: Error<P::Input>,
+    {
+        struct Cut<A, E>(A, PhantomData<E>);
+
+        impl<A, E> Pattern<E> for Cut<A, E>
+            where
+                A: Pattern<E>,
+                E: Error<A::Input>,
+        {
+            type Input = A::Input;
+            type Output = A::Output;
+
+            fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+                let (out, fail) = self.0.parse(stream)?;
+                stream.cut();
+                Ok((out, fail))
+            }
+
+            fn cloned(&self) -> Self where Self: Sized {
+                Self(self.0.cloned(), PhantomData)
+            }
+
+            fn describe(&self, grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+                self.0.describe(grammar)
+            }
+        }
+
+        Parser::from_pat(Cut(self.pat, PhantomData))
+    }
 }
@@ -0,0 +1,205 @@
+//! Parsing types straight from their definitions with `#[derive(Parse)]`.
+//!
+//! ```ignore
+//! #[derive(Parse)]
+//! enum Value {
+//!     #[parze(keyword = "null")]
+//!     Null,
+//!     Num(i64),
+//!     #[parze(just = "[", after = "]")]
+//!     List(#[parze(sep = ",")] Vec<Value>),
+//! }
+//!
+//! let value = Value::parser::<DefaultError<char>>().parse("[1, null, [2]]".chars());
+//! ```
+//!
+//! Enum variants are tried in order. Each variant (or struct) parses its fields in order, with whitespace allowed
+//! around every field and token. The following attributes are understood, on variants, structs and fields:
+//!
+//! - `#[parze(just = "...")]`: the given text must appear first
+//! - `#[parze(keyword = "...")]`: the given keyword must appear first (see `text::keyword`)
+//! - `#[parze(after = "...")]`: the given text must appear last
+//!
+//! And on fields only:
+//!
+//! - `#[parze(sep = "...")]`: for `Vec` fields, the text that separates elements
+//!
+//! Fields of type `Vec<T>`, `Option<T>` and `Box<T>` parse as many, an optional or a boxed `T`. Any other field type
+//! must itself implement `Parse`. The tokens attached to an `Option` field are only expected when it is present.
+//!
+//! Types may refer to each other recursively, but not left-recursively: a variant must not begin with a field that can
+//! lead straight back to the same type.
+
+use std::{
+    any::{Any, TypeId},
+    cell::{OnceCell, RefCell},
+    collections::HashMap,
+    marker::PhantomData,
+    rc::Rc,
+    str::FromStr,
+};
+use crate::{
+    text,
+    Parser,
+    Pattern,
+    Error,
+    Stream,
+    ParseResult,
+    Fail,
+};
+
+pub use parze_derive::Parse;
+
+/// A type that can be parsed from text.
+pub trait Parse: Sized {
+    fn parser<E: Error<char> + 'static>() -> Parser<impl Pattern<E, Input=char, Output=Self> + 'static, E>;
+}
+
+// Lazy
+
+// The parser built by `lazy`, which is shared by every `lazy` parser for the same type so that it is only built once
+type Cache<E, T> = Rc<OnceCell<Box<dyn Pattern<E, Input=char, Output=T>>>>;
+
+thread_local! {
+    // The cache for each pair of parsed type and error type. A recursive type's parser refers back to its own cache,
+    // so these live as long as the thread does.
+    static CACHES: RefCell<HashMap<TypeId, Rc<dyn Any>>> = RefCell::new(HashMap::new());
+}
+
+/// The parser for `T`, built the first time it is used.
+///
+/// Since the parser's type does not depend on how `T` is parsed, this allows types to be parsed recursively. Every
+/// `lazy::<T, E>()` on a thread shares the same parser, so it is built once however deeply `T` nests.
+pub fn lazy<T, E>() -> Parser<impl Pattern<E, Input=char, Output=T> + 'static, E>
+    where
+        T: Parse + 'static,
+        E: Error<char> + 'static,
+{
+    struct Lazy<T, E>(Cache<E, T>);
+
+    impl<T, E> Pattern<E> for Lazy<T, E>
+        where
+            T: Parse + 'static,
+            E: Error<char> + 'static,
+    {
+        type Input = char;
+        type Output = T;
+
+        fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+            self.0.get_or_init(|| Box::new(T::parser::<E>().pat)).parse(stream)
+        }
+
+        fn cloned(&self) -> Self where Self: Sized {
+            Self(self.0.clone())
+        }
+    }
+
+    let cache = CACHES.with(|caches| {
+        caches
+            .borrow_mut()
+            .entry(TypeId::of::<(T, E)>())
+            .or_insert_with(|| Rc::new(OnceCell::<Box<dyn Pattern<E, Input=char, Output=T>>>::new()))
+            .clone()
+    });
+    Parser::from_pat(Lazy(cache.downcast().ok().unwrap()))
+}
+
+// Convert
+
+// Parses text with `inner`, then converts it with `f`, failing at the start of the text if that is not possible
+fn convert<P, O, E>(label: &'static str, inner: Parser<P, E>, f: fn(P::Output) -> Option<O>) -> Parser<impl Pattern<E, Input=char, Output=O> + 'static, E>
+    where
+        P: Pattern<E, Input=char> + 'static,
+        O: 'static,
+        E: Error<char> + 'static,
+{
+    struct Convert<A, O, E>(&'static str, Rc<A>, fn(A::Output) -> Option<O>, PhantomData<E>)
+        where A: Pattern<E>;
+
+    impl<A, O, E> Pattern<E> for Convert<A, O, E>
+        where
+            A: Pattern<E, Input=char>,
+            E: Error<char>,
+    {
+        type Input = char;
+        type Output = O;
+
+        fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+            let mut start = *stream;
+            let (out, fail) = self.1.parse(stream)?;
+            match (self.2)(out) {
+                Some(out) => Ok((out, fail)),
+                None => {
+                    *stream = start;
+                    let (idx, sym) = start.next().unwrap();
+                    Err(Fail::one(idx, E::unexpected_sym(sym, start.span_from(idx)).expected_label(self.0)))
+                },
+            }
+        }
+
+        fn cloned(&self) -> Self where Self: Sized {
+            Self(self.0, self.1.clone(), self.2, PhantomData)
+        }
+    }
+
+    Parser::from_pat(Convert(label, Rc::new(inner.pat), f, PhantomData)).padded()
+}
+
+fn number<T, E>(label: &'static str, digits: Parser<impl Pattern<E, Input=char, Output=String> + 'static, E>) -> Parser<impl Pattern<E, Input=char, Output=T> + 'static, E>
+    where
+        T: FromStr + 'static,
+        E: Error<char> + 'static,
+{
+    let text = crate::primitives::just('-').or_not().then(digits);
+    convert(label, text, |(neg, s)| (if neg.is_some() { format!("-{}", s) } else { s }).parse().ok())
+}
+
+fn int<T: FromStr + 'static, E: Error<char> + 'static>(label: &'static str) -> Parser<impl Pattern<E, Input=char, Output=T> + 'static, E> {
+    number(label, text::int(10))
+}
+
+fn float<T: FromStr + 'static, E: Error<char> + 'static>(label: &'static str) -> Parser<impl Pattern<E, Input=char, Output=T> + 'static, E> {
+    number(label, text::float())
+}
+
+macro_rules! impl_parse {
+    ($($ty:ident => $f:ident;)*) => {
+        $(
+            impl Parse for $ty {
+                fn parser<E: Error<char> + 'static>() -> Parser<impl Pattern<E, Input=char, Output=Self> + 'static, E> {
+                    $f(stringify!($ty))
+                }
+            }
+        )*
+    };
+}
+
+impl_parse! {
+    u8 => int; u16 => int; u32 => int; u64 => int; u128 => int; usize => int;
+    i8 => int; i16 => int; i32 => int; i64 => int; i128 => int; isize => int;
+    f32 => float; f64 => float;
+}
+
+/// Parses an identifier.
+impl Parse for String {
+    fn parser<E: Error<char> + 'static>() -> Parser<impl Pattern<E, Input=char, Output=Self> + 'static, E> {
+        text::ident().padded()
+    }
+}
+
+/// Parses `true` or `false`.
+impl Parse for bool {
+    fn parser<E: Error<char> + 'static>() -> Parser<impl Pattern<E, Input=char, Output=Self> + 'static, E> {
+        text::keyword("true").to(true).or(text::keyword("false").to(false)).padded()
+    }
+}
+
+#[doc(hidden)]
+pub fn token<E: Error<char> + 'static>(token: &'static str) -> Parser<impl Pattern<E, Input=char, Output=()> + 'static, E> {
+    crate::primitives::seq(token.chars().collect::<Vec<_>>()).to(()).padded()
+}
+
+#[doc(hidden)]
+pub fn keyword<E: Error<char> + 'static>(keyword: &'static str) -> Parser<impl Pattern<E, Input=char, Output=()> + 'static, E> {
+    text::keyword(keyword).padded()
+}
@@ -1,6 +1,11 @@
-use std::marker::PhantomData;
+use std::{
+    marker::PhantomData,
+    borrow::Borrow,
+    ops::Range,
+};
 use crate::{
     util::attempt,
+    grammar::{Grammar, Node},
     Parser,
     Pattern,
     Error,
@@ -30,7 +35,7 @@ pub fn any<I, E>() -> Parser<impl Pattern<E, Input=I, Output=I>, E>
             attempt(stream, |stream| {
                 match stream.next() {
                     Some((_, sym)) => Ok((sym.clone(), Fail::none())),
-                    None => Err(Fail::one(!0, E::unexpected_end())),
+                    None => Err(Fail::one(stream.end_index(), E::unexpected_end(stream.end_span()))),
                 }
             })
         }
@@ -38,6 +43,10 @@ pub fn any<I, E>() -> Parser<impl Pattern<E, Input=I, Output=I>, E>
         fn cloned(&self) -> Self where Self: Sized {
             Self(PhantomData)
         }
+
+        fn describe(&self, _grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+            Node::Any
+        }
     }
 
     Parser::from_pat(Any(PhantomData))
@@ -73,6 +82,10 @@ pub fn end<I, E>() -> Parser<impl Pattern<E, Input=I, Output=()>, E>
         fn cloned(&self) -> Self where Self: Sized {
             Self(PhantomData)
         }
+
+        fn describe(&self, _grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+            Node::End
+        }
     }
 
     Parser::from_pat(End(PhantomData))
@@ -103,7 +116,7 @@ pub fn just<I, J, E>(item: J) -> Parser<impl Pattern<E, Input=I, Output=I>, E>
                 match stream.next() {
                     Some((_, sym)) if sym == &self.0 => Ok((sym.clone(), Fail::none())),
                     Some((idx, sym)) => Err(Fail::one(idx, E::unexpected_sym(sym, stream.span_from(checkpoint)).expected(self.0.clone().into()))),
-                    None => Err(Fail::one(!0, E::unexpected_end())),
+                    None => Err(Fail::one(stream.end_index(), E::unexpected_end(stream.end_span()).expected(self.0.clone().into()))),
                 }
             })
         }
@@ -111,6 +124,10 @@ pub fn just<I, J, E>(item: J) -> Parser<impl Pattern<E, Input=I, Output=I>, E>
         fn cloned(&self) -> Self where Self: Sized {
             Self(self.0.clone(), PhantomData)
         }
+
+        fn describe(&self, _grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+            Node::Just(self.0.clone().into())
+        }
     }
 
     Parser::from_pat(Just(item, PhantomData))
@@ -143,7 +160,7 @@ pub fn seq<I, J, E>(item: impl IntoIterator<Item=J>) -> Parser<impl Pattern<E, I
                     match stream.next() {
                         Some((_, sym)) if sym == item => syms.push(sym.clone()),
                         Some((idx, sym)) => return Err(Fail::one(idx, E::unexpected_sym(sym, stream.span_from(checkpoint)).expected(item.clone().into()))),
-                        None => return Err(Fail::one(!0, E::unexpected_end())),
+                        None => return Err(Fail::one(stream.end_index(), E::unexpected_end(stream.end_span()).expected(item.clone().into()))),
                     }
                 }
                 Ok((syms, Fail::none()))
@@ -153,6 +170,10 @@ pub fn seq<I, J, E>(item: impl IntoIterator<Item=J>) -> Parser<impl Pattern<E, I
         fn cloned(&self) -> Self where Self: Sized {
             Self(self.0.clone(), PhantomData)
         }
+
+        fn describe(&self, _grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+            Node::Seq(self.0.iter().map(|item| item.clone().into()).collect())
+        }
     }
 
     Parser::from_pat(Seq(item.into_iter().collect(), PhantomData))
@@ -160,22 +181,42 @@ pub fn seq<I, J, E>(item: impl IntoIterator<Item=J>) -> Parser<impl Pattern<E, I
 
 // NestedParse
 
+/// Parses the symbols nested within a single symbol, such as the contents of a token tree group.
+///
+/// Spans produced by the nested parse all refer to the span of the enclosing symbol. Use `nested_parse_with` to give
+/// nested symbols more precise spans.
 pub fn nested_parse<P, I, Ins, J, E>(f: impl Fn(I) -> Option<(Parser<P, E>, Ins)> + Clone) -> Parser<impl Pattern<E, Input=I, Output=J>, E>
     where
         P: Pattern<E, Input=I, Output=J>,
         I: Clone,
         Ins: IntoIterator<Item=I>,
-        Ins::IntoIter: Clone,
         E: Error<I>,
 {
-    struct NestedParse<F, P, I, Ins, J, E>(F, PhantomData<(P, I, Ins, J, E)>);
+    nested_parse_with(f, |outer: &Range<usize>, _| outer.clone())
+}
+
+/// Like `nested_parse`, but `translate` gives the span of each nested symbol from the span of the enclosing symbol
+/// and the nested symbol's index.
+///
+/// Failures within the nested parse are reported at the position of the enclosing symbol.
+pub fn nested_parse_with<P, I, Ins, J, E>(
+    f: impl Fn(I) -> Option<(Parser<P, E>, Ins)> + Clone,
+    translate: impl Fn(&Range<usize>, usize) -> Range<usize> + Clone,
+) -> Parser<impl Pattern<E, Input=I, Output=J>, E>
+    where
+        P: Pattern<E, Input=I, Output=J>,
+        I: Clone,
+        Ins: IntoIterator<Item=I>,
+        E: Error<I>,
+{
+    struct NestedParse<F, T, P, I, Ins, J, E>(F, T, PhantomData<(P, I, Ins, J, E)>);
 
-    impl<I, Ins, J, F, P, E> Pattern<E> for NestedParse<F, P, I, Ins, J, E>
+    impl<I, Ins, J, F, T, P, E> Pattern<E> for NestedParse<F, T, P, I, Ins, J, E>
         where
             I: Clone,
             Ins: IntoIterator<Item=I>,
-            Ins::IntoIter: Clone,
             F: Fn(I) -> Option<(Parser<P, E>, Ins)> + Clone,
+            T: Fn(&Range<usize>, usize) -> Range<usize> + Clone,
             P: Pattern<E, Input=I, Output=J>,
             E: Error<I>,
     {
@@ -187,23 +228,33 @@ pub fn nested_parse<P, I, Ins, J, E>(f: impl Fn(I) -> Option<(Parser<P, E>, Ins)
             attempt(stream, |stream| {
                 match stream.next() {
                     Some((idx, sym)) => match self.0(sym.clone()) {
-                        Some((parser, ins)) => match parser.parse_inner(&ins.into_iter().collect::<Vec<_>>()) {
-                            Ok((out, _)) => Ok((out, Fail::none())),
-                            Err(err) => Err(err.add_index(idx)), // This is a total hack
+                        Some((parser, ins)) => {
+                            let outer = stream.range_from(checkpoint);
+                            let ins = ins.into_iter().collect::<Vec<_>>();
+                            let spans = (0..ins.len()).map(|i| (self.1)(&outer, i)).collect::<Vec<_>>();
+                            match parser.pat.parse(&mut Stream::with_spans(&ins, &spans).nested()) {
+                                Ok((out, fail)) => Ok((out, fail.at(idx))),
+                                Err(fail) => Err(fail.at(idx)),
+                            }
                         },
                         None => Err(Fail::one(idx, E::unexpected_sym(sym, stream.span_from(checkpoint)))),
                     },
-                    None => Err(Fail::one(!0, E::unexpected_end())),
+                    None => Err(Fail::one(stream.end_index(), E::unexpected_end(stream.end_span()))),
                 }
             })
         }
 
         fn cloned(&self) -> Self where Self: Sized {
-            Self(self.0.clone(), PhantomData)
+            Self(self.0.clone(), self.1.clone(), PhantomData)
+        }
+
+        // The nested parser depends on the symbol, so only the symbol itself can be described
+        fn describe(&self, _grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+            Node::Any
         }
     }
 
-    Parser::from_pat(NestedParse(f, PhantomData))
+    Parser::from_pat(NestedParse(f, translate, PhantomData))
 }
 
 // PermitMap
@@ -232,7 +283,7 @@ pub fn permit_map<I, J, E>(f: impl Fn(I) -> Option<J> + Clone) -> Parser<impl Pa
                         Some(out) => Ok((out, Fail::none())),
                         None => Err(Fail::one(idx, E::unexpected_sym(sym, stream.span_from(checkpoint)))),
                     },
-                    None => Err(Fail::one(!0, E::unexpected_end())),
+                    None => Err(Fail::one(stream.end_index(), E::unexpected_end(stream.end_span()))),
                 }
             })
         }
@@ -240,11 +291,80 @@ pub fn permit_map<I, J, E>(f: impl Fn(I) -> Option<J> + Clone) -> Parser<impl Pa
         fn cloned(&self) -> Self where Self: Sized {
             Self(self.0.clone(), PhantomData)
         }
+
+        fn describe(&self, _grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+            Node::Any
+        }
     }
 
     Parser::from_pat(PermitMap(f, PhantomData))
 }
 
+// Select
+
+/// Like `permit_map`, but reports each of `labels` as expected upon failure. Usually created with `select!`.
+pub fn select<I, J, E>(labels: &'static [&'static str], f: impl Fn(I) -> Option<J> + Clone) -> Parser<impl Pattern<E, Input=I, Output=J>, E>
+    where
+        I: Clone,
+        E: Error<I>,
+{
+    struct Select<F, I, J, E>(&'static [&'static str], F, PhantomData<(I, J, E)>);
+
+    impl<I, J, F, E> Pattern<E> for Select<F, I, J, E>
+        where
+            I: Clone,
+            F: Fn(I) -> Option<J> + Clone,
+            E: Error<I>,
+    {
+        type Input = I;
+        type Output = J;
+
+        fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+            let checkpoint = stream.checkpoint();
+            attempt(stream, |stream| {
+                let (idx, err) = match stream.next() {
+                    Some((idx, sym)) => match (self.1)(sym.clone()) {
+                        Some(out) => return Ok((out, Fail::none())),
+                        None => (idx, E::unexpected_sym(sym, stream.span_from(checkpoint))),
+                    },
+                    None => (stream.end_index(), E::unexpected_end(stream.end_span())),
+                };
+                Err(Fail::one(idx, self.0.iter().fold(err, |err, label| err.expected_label(label))))
+            })
+        }
+
+        fn cloned(&self) -> Self where Self: Sized {
+            Self(self.0, self.1.clone(), PhantomData)
+        }
+
+        fn describe(&self, _grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+            Node::Choice(self.0.iter().map(|label| Node::Label(label)).collect())
+        }
+    }
+
+    Parser::from_pat(Select(labels, f, PhantomData))
+}
+
+/// Parses a single symbol matching one of the given patterns, producing the corresponding output.
+///
+/// Each pattern is reported as expected upon failure.
+///
+/// ```ignore
+/// let ident = select! { Token::Ident(s) => s, Token::Num(n) if n >= 0 => n.to_string() };
+/// ```
+#[macro_export]
+macro_rules! select {
+    ($($($pat:pat)|+ $(if $guard:expr)? => $out:expr),+ $(,)?) => {
+        $crate::primitives::select(
+            &[$(stringify!($($pat)|+)),+],
+            move |sym| match sym {
+                $($($pat)|+ $(if $guard)? => ::std::option::Option::Some($out),)+
+                _ => ::std::option::Option::None,
+            },
+        )
+    };
+}
+
 // Permit
 
 pub fn permit<I, E>(f: impl Fn(&I) -> bool + Clone) -> Parser<impl Pattern<E, Input=I, Output=I>, E>
@@ -254,3 +374,267 @@ pub fn permit<I, E>(f: impl Fn(&I) -> bool + Clone) -> Parser<impl Pattern<E, In
 {
     permit_map(move |sym| if f(&sym) { Some(sym) } else { None })
 }
+
+// OneOf
+
+pub fn one_of<I, E>(items: impl IntoIterator<Item=impl Borrow<I>>) -> Parser<impl Pattern<E, Input=I, Output=I>, E>
+    where
+        I: PartialEq + Clone,
+        E: Error<I>,
+{
+    struct OneOf<I, E>(Vec<I>, PhantomData<E>);
+
+    impl<I, E> Pattern<E> for OneOf<I, E>
+        where
+            I: PartialEq + Clone,
+            E: Error<I>,
+    {
+        type Input = I;
+        type Output = I;
+
+        fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+            let checkpoint = stream.checkpoint();
+            attempt(stream, |stream| {
+                match stream.next() {
+                    Some((_, sym)) if self.0.contains(sym) => Ok((sym.clone(), Fail::none())),
+                    Some((idx, sym)) => Err(Fail::one(idx, self.0
+                        .iter()
+                        .fold(E::unexpected_sym(sym, stream.span_from(checkpoint)), |err, item| err.expected(item.clone().into())))),
+                    None => Err(Fail::one(stream.end_index(), self.0
+                        .iter()
+                        .fold(E::unexpected_end(stream.end_span()), |err, item| err.expected(item.clone().into())))),
+                }
+            })
+        }
+
+        fn cloned(&self) -> Self where Self: Sized {
+            Self(self.0.clone(), PhantomData)
+        }
+
+        fn describe(&self, _grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+            Node::OneOf(self.0.iter().map(|item| item.clone().into()).collect())
+        }
+    }
+
+    Parser::from_pat(OneOf(items.into_iter().map(|item| item.borrow().clone()).collect(), PhantomData))
+}
+
+// NoneOf
+
+pub fn none_of<I, E>(items: impl IntoIterator<Item=impl Borrow<I>>) -> Parser<impl Pattern<E, Input=I, Output=I>, E>
+    where
+        I: PartialEq + Clone,
+        E: Error<I>,
+{
+    struct NoneOf<I, E>(Vec<I>, PhantomData<E>);
+
+    impl<I, E> Pattern<E> for NoneOf<I, E>
+        where
+            I: PartialEq + Clone,
+            E: Error<I>,
+    {
+        type Input = I;
+        type Output = I;
+
+        fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+            let checkpoint = stream.checkpoint();
+            attempt(stream, |stream| {
+                match stream.next() {
+                    Some((idx, sym)) if self.0.contains(sym) => Err(Fail::one(idx, E::unexpected_sym(sym, stream.span_from(checkpoint)))),
+                    Some((_, sym)) => Ok((sym.clone(), Fail::none())),
+                    None => Err(Fail::one(stream.end_index(), E::unexpected_end(stream.end_span()))),
+                }
+            })
+        }
+
+        fn cloned(&self) -> Self where Self: Sized {
+            Self(self.0.clone(), PhantomData)
+        }
+
+        fn describe(&self, _grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+            Node::NoneOf(self.0.iter().map(|item| item.clone().into()).collect())
+        }
+    }
+
+    Parser::from_pat(NoneOf(items.into_iter().map(|item| item.borrow().clone()).collect(), PhantomData))
+}
+
+// Filter
+
+pub fn filter<I, E>(label: &'static str, f: impl Fn(&I) -> bool + Clone) -> Parser<impl Pattern<E, Input=I, Output=I>, E>
+    where
+        I: Clone,
+        E: Error<I>,
+{
+    struct Filter<F, I, E>(&'static str, F, PhantomData<(I, E)>);
+
+    impl<I, F, E> Pattern<E> for Filter<F, I, E>
+        where
+            I: Clone,
+            F: Fn(&I) -> bool + Clone,
+            E: Error<I>,
+    {
+        type Input = I;
+        type Output = I;
+
+        fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+            let checkpoint = stream.checkpoint();
+            attempt(stream, |stream| {
+                match stream.next() {
+                    Some((_, sym)) if (self.1)(sym) => Ok((sym.clone(), Fail::none())),
+                    Some((idx, sym)) => Err(Fail::one(idx, E::unexpected_sym(sym, stream.span_from(checkpoint)).expected_label(self.0))),
+                    None => Err(Fail::one(stream.end_index(), E::unexpected_end(stream.end_span()).expected_label(self.0))),
+                }
+            })
+        }
+
+        fn cloned(&self) -> Self where Self: Sized {
+            Self(self.0, self.1.clone(), PhantomData)
+        }
+
+        fn describe(&self, _grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+            Node::Label(self.0)
+        }
+    }
+
+    Parser::from_pat(Filter(label, f, PhantomData))
+}
+
+// TakeWhile
+
+/// Skips symbols for as long as `f` holds, returning how many were skipped.
+///
+/// Use `map_with_span` to find out where the skipped symbols were.
+pub fn take_while<I, E>(f: impl Fn(&I) -> bool + Clone) -> Parser<impl Pattern<E, Input=I, Output=usize>, E>
+    where E: Error<I>
+{
+    struct TakeWhile<F, I, E>(F, PhantomData<(I, E)>);
+
+    impl<I, F, E> Pattern<E> for TakeWhile<F, I, E>
+        where
+            F: Fn(&I) -> bool + Clone,
+            E: Error<I>,
+    {
+        type Input = I;
+        type Output = usize;
+
+        fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+            let mut n = 0;
+            while stream.peek().is_some_and(&self.0) {
+                stream.next();
+                n += 1;
+            }
+            Ok((n, Fail::none()))
+        }
+
+        fn cloned(&self) -> Self where Self: Sized {
+            Self(self.0.clone(), PhantomData)
+        }
+
+        fn describe(&self, _grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+            Node::Repeated(Box::new(Node::Any))
+        }
+    }
+
+    Parser::from_pat(TakeWhile(f, PhantomData))
+}
+
+// TakeUntil
+
+/// Skips symbols until `until` would succeed, returning how many were skipped. `until` itself is not consumed.
+///
+/// Fails if the end of input is reached without `until` succeeding.
+pub fn take_until<I, E>(until: Parser<impl Pattern<E, Input=I>, E>) -> Parser<impl Pattern<E, Input=I, Output=usize>, E>
+    where E: Error<I>
+{
+    struct TakeUntil<A, E>(A, PhantomData<E>);
+
+    impl<I, A, E> Pattern<E> for TakeUntil<A, E>
+        where
+            A: Pattern<E, Input=I>,
+            E: Error<I>,
+    {
+        type Input = I;
+        type Output = usize;
+
+        fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+            attempt(stream, |stream| {
+                let mut n = 0;
+                loop {
+                    // Only looking ahead, so anything `until` writes is discarded. It is held back by a choice of its
+                    // own, which a cut within `until` cannot settle, but the cut itself is kept
+                    let mut cut = false;
+                    let until = stream.alternative(|stream| {
+                        let mut ahead = *stream;
+                        let result = ahead.choice(|ahead| self.0.parse(ahead));
+                        if ahead.is_cut() {
+                            cut = true;
+                            stream.cut();
+                        }
+                        result
+                    });
+                    match until {
+                        Ok(_) => {
+                            // The cut lasts beyond `alternative`, committing whatever `take_until` is part of
+                            if cut {
+                                stream.cut();
+                            }
+                            break Ok((n, Fail::none()));
+                        },
+                        Err(fail) if fail.is_committed() => break Err(fail),
+                        Err(fail) => if stream.next().is_none() {
+                            break Err(fail);
+                        },
+                    }
+                    n += 1;
+                }
+            })
+        }
+
+        fn cloned(&self) -> Self where Self: Sized {
+            Self(self.0.cloned(), PhantomData)
+        }
+
+        // `until` is only looked for, not consumed
+        fn describe(&self, _grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+            Node::Repeated(Box::new(Node::Any))
+        }
+    }
+
+    Parser::from_pat(TakeUntil(until.pat, PhantomData))
+}
+
+// Take
+
+/// Skips exactly `n` symbols of any kind, returning how many were skipped.
+///
+/// Use `map_with_span` to find out where the skipped symbols were.
+pub fn take<I, E>(n: usize) -> Parser<impl Pattern<E, Input=I, Output=usize>, E>
+    where E: Error<I>
+{
+    struct Take<I, E>(usize, PhantomData<(I, E)>);
+
+    impl<I, E> Pattern<E> for Take<I, E>
+        where E: Error<I>
+    {
+        type Input = I;
+        type Output = usize;
+
+        fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+            match stream.take_slice(self.0) {
+                Some(_) => Ok((self.0, Fail::none())),
+                None => Err(Fail::one(stream.end_index(), E::unexpected_end(stream.end_span()))),
+            }
+        }
+
+        fn cloned(&self) -> Self where Self: Sized {
+            Self(self.0, PhantomData)
+        }
+
+        fn describe(&self, _grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+            Node::any_n(self.0)
+        }
+    }
+
+    Parser::from_pat(Take(n, PhantomData))
+}
@@ -0,0 +1,236 @@
+//! Profiling of named parsers, for finding the parts of a grammar that parsing spends its time in.
+//!
+//! ```ignore
+//! let profile = Profile::new().on_finish(|profile| eprintln!("{}", profile.report()));
+//! let stmt = select_stmt.profiled(&profile, "select")
+//!     .or(insert_stmt.profiled(&profile, "insert"))
+//!     .profiled(&profile, "stmt");
+//!
+//! // Prints the report once the parse is done
+//! stmt.parse(tokens);
+//! ```
+//!
+//! Inputs consumed by a parser count as discarded when parsing later backtracks before them, such as when a later
+//! part of the same alternative fails. Backtracking is noticed when a profiled parser starts or finishes somewhere
+//! before the inputs were consumed, at any point in the same parse.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::Write,
+    marker::PhantomData,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+use crate::{
+    Parser,
+    Pattern,
+    Error,
+    Stream,
+    ParseResult,
+    grammar::{Grammar, Node},
+};
+
+/// The statistics gathered for one named parser.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub calls: usize,
+    pub successes: usize,
+    pub failures: usize,
+    /// The number of inputs consumed by successful calls.
+    pub consumed: usize,
+    /// The number of consumed inputs that were later backtracked over.
+    pub discarded: usize,
+    /// The time spent in calls, not counting recursive calls twice.
+    pub time: Duration,
+}
+
+#[derive(Default)]
+struct Rule {
+    stats: Stats,
+    // The number of calls underway, so that recursive calls are not timed
+    active: usize,
+}
+
+type Callback = Box<dyn Fn(&Profile)>;
+
+#[derive(Default)]
+struct Table {
+    rules: RefCell<HashMap<&'static str, Rule>>,
+    // The inputs consumed by successful calls that have not yet been backtracked over, ordered by their end
+    consumed: RefCell<Vec<(&'static str, usize, usize)>>,
+    on_finish: RefCell<Option<Callback>>,
+}
+
+impl Table {
+    // Counts everything consumed beyond `pos` as discarded
+    fn backtrack(&self, pos: usize) {
+        let mut consumed = self.consumed.borrow_mut();
+        let mut rules = self.rules.borrow_mut();
+        while let Some((name, start, end)) = consumed.last_mut() {
+            if *end <= pos {
+                break;
+            }
+            rules.entry(*name).or_default().stats.discarded += *end - pos.max(*start);
+            if *start < pos {
+                *end = pos;
+            } else {
+                consumed.pop();
+            }
+        }
+    }
+}
+
+thread_local! {
+    // The profiles used by each parse underway, innermost last
+    static SESSIONS: RefCell<Vec<Vec<Profile>>> = const { RefCell::new(Vec::new()) };
+}
+
+// Runs `f` as a whole parse, finishing every profile used within it once it is done
+pub(crate) fn session<R>(f: impl FnOnce() -> R) -> R {
+    SESSIONS.with(|sessions| sessions.borrow_mut().push(Vec::new()));
+    let r = f();
+    let profiles = SESSIONS.with(|sessions| sessions.borrow_mut().pop()).unwrap_or_default();
+    for profile in profiles {
+        profile.finish();
+    }
+    r
+}
+
+/// A set of statistics for named parsers. Cloning a `Profile` shares its statistics.
+#[derive(Clone, Default)]
+pub struct Profile(Rc<Table>);
+
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Calls `f` after every parse that used this profile, such as to print the report. Each item produced by
+    /// `parse_iter` counts as a parse of its own.
+    pub fn on_finish(self, f: impl Fn(&Profile) + 'static) -> Self {
+        *self.0.on_finish.borrow_mut() = Some(Box::new(f));
+        self
+    }
+
+    // Notes that the parse underway uses this profile
+    fn enter(&self) {
+        SESSIONS.with(|sessions| {
+            if let Some(profiles) = sessions.borrow_mut().last_mut() {
+                if !profiles.iter().any(|profile| Rc::ptr_eq(&profile.0, &self.0)) {
+                    profiles.push(self.clone());
+                }
+            }
+        });
+    }
+
+    fn finish(&self) {
+        // Positions mean nothing to the next parse
+        self.0.consumed.borrow_mut().clear();
+        if let Some(f) = &*self.0.on_finish.borrow() {
+            f(self);
+        }
+    }
+
+    /// The statistics for the parser named `name`, if it has been called.
+    pub fn stats(&self, name: &str) -> Option<Stats> {
+        self.0.rules.borrow().get(name).map(|rule| rule.stats.clone())
+    }
+
+    /// Discards all statistics.
+    pub fn reset(&self) {
+        self.0.rules.borrow_mut().clear();
+        self.0.consumed.borrow_mut().clear();
+    }
+
+    /// A table of the statistics for each parser, slowest first.
+    pub fn report(&self) -> String {
+        let rules = self.0.rules.borrow();
+        let mut rows = rules.iter().map(|(name, rule)| (*name, &rule.stats)).collect::<Vec<_>>();
+        rows.sort_by(|(a_name, a), (b_name, b)| b.time.cmp(&a.time).then(a_name.cmp(b_name)));
+
+        let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0).max(4);
+        let mut out = String::new();
+        writeln!(out, "{:width$} {:>10} {:>10} {:>10} {:>10} {:>10} {:>12}", "rule", "calls", "successes", "failures", "consumed", "discarded", "time", width = width).unwrap();
+        for (name, stats) in rows {
+            writeln!(
+                out,
+                "{:width$} {:>10} {:>10} {:>10} {:>10} {:>10} {:>12}",
+                name, stats.calls, stats.successes, stats.failures, stats.consumed, stats.discarded, format!("{:.3?}", stats.time),
+                width = width,
+            ).unwrap();
+        }
+        out
+    }
+}
+
+// Profiled
+
+impl<P, E> Parser<P, E> {
+    /// Gathers statistics for this parser in `profile` under `name`. Parsers with the same name share statistics.
+    pub fn profiled(self, profile: &Profile, name: &'static str) -> Parser<impl Pattern<E, Input=P::Input, Output=P::Output>, E>
+        where
+            P: Pattern<E>,
+            E: Error<P::Input>,
+    {
+        struct Profiled<A, E>(&'static str, A, Profile, PhantomData<E>);
+
+        impl<A, E> Pattern<E> for Profiled<A, E>
+            where
+                A: Pattern<E>,
+                E: Error<A::Input>,
+        {
+            type Input = A::Input;
+            type Output = A::Output;
+
+            fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+                let table = &self.2.0;
+                let start = stream.checkpoint() as usize;
+                self.2.enter();
+                table.backtrack(start);
+                let outermost = {
+                    let mut rules = table.rules.borrow_mut();
+                    let rule = rules.entry(self.0).or_default();
+                    rule.stats.calls += 1;
+                    rule.active += 1;
+                    rule.active == 1
+                };
+
+                let time = Instant::now();
+                let result = self.1.parse(stream);
+                let time = time.elapsed();
+
+                let end = stream.checkpoint() as usize;
+                table.backtrack(end);
+                {
+                    let mut rules = table.rules.borrow_mut();
+                    let rule = rules.entry(self.0).or_default();
+                    rule.active -= 1;
+                    if outermost {
+                        rule.stats.time += time;
+                    }
+                    if result.is_ok() {
+                        rule.stats.successes += 1;
+                        rule.stats.consumed += end - start;
+                    } else {
+                        rule.stats.failures += 1;
+                    }
+                }
+                if result.is_ok() && end > start {
+                    table.consumed.borrow_mut().push((self.0, start, end));
+                }
+                result
+            }
+
+            fn cloned(&self) -> Self where Self: Sized {
+                Self(self.0, self.1.cloned(), self.2.clone(), PhantomData)
+            }
+
+            fn describe(&self, grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+                self.1.describe(grammar)
+            }
+        }
+
+        Parser::from_pat(Profiled(name, self.pat, profile.clone(), PhantomData))
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    cell::RefCell,
+    marker::PhantomData,
+    rc::Rc,
+};
+use regex_automata::{
+    hybrid::dfa::{DFA, Cache},
+    util::start,
+    Anchored,
+};
+use crate::{
+    text::Character,
+    util::attempt,
+    grammar::{Grammar, Node},
+    Parser,
+    Pattern,
+    Error,
+    Stream,
+    ParseResult,
+    Fail,
+};
+
+/// A symbol that a regular expression can be matched against.
+pub trait RegexSymbol: Character {
+    /// The bytes that this symbol contributes to the haystack.
+    fn bytes(self, buf: &mut [u8; 4]) -> &[u8];
+}
+
+impl RegexSymbol for char {
+    fn bytes(self, buf: &mut [u8; 4]) -> &[u8] {
+        self.encode_utf8(buf).as_bytes()
+    }
+}
+
+impl RegexSymbol for u8 {
+    fn bytes(self, buf: &mut [u8; 4]) -> &[u8] {
+        buf[0] = self;
+        &buf[..1]
+    }
+}
+
+// Walks the lazy DFA over the stream, returning the number of symbols in the longest-preferred match.
+// The DFA reports matches one byte late, so a match is only recorded on the first byte of the following symbol (or
+// at the end of input), which also guarantees that it ends on a symbol boundary.
+fn find<C: RegexSymbol>(dfa: &DFA, cache: &mut Cache, stream: &Stream<C>) -> Option<usize> {
+    let mut buf = [0; 4];
+    let config = start::Config::new()
+        .anchored(Anchored::Yes)
+        .look_behind(stream.prev().map(|c| *c.bytes(&mut buf).last().unwrap()));
+    let mut sid = dfa.start_state(cache, &config).ok()?;
+    let mut last_match = None;
+
+    for (n, (_, sym)) in stream.enumerate() {
+        for (i, byte) in sym.bytes(&mut buf).iter().enumerate() {
+            sid = dfa.next_state(cache, sid, *byte).ok()?;
+            if i == 0 && sid.is_match() {
+                last_match = Some(n);
+            }
+            if sid.is_dead() || sid.is_quit() {
+                return last_match;
+            }
+        }
+    }
+
+    match dfa.next_eoi_state(cache, sid) {
+        Ok(sid) if sid.is_match() => Some(stream.count()),
+        _ => last_match,
+    }
+}
+
+// Regex
+
+/// Matches the regular expression `pattern`, anchored at the current position.
+///
+/// The output is the matched text along with its span. On failure, `pattern` is reported as the expected label.
+///
+/// Unicode word boundaries (`\b`) are only supported over ASCII text: matching gives up upon meeting a non-ASCII
+/// symbol next to one. Use `(?-u:\b)` for ASCII word boundaries instead.
+///
+/// # Panics
+///
+/// Panics if `pattern` is not a valid regular expression.
+pub fn regex<C, E>(pattern: &'static str) -> Parser<impl Pattern<E, Input=C, Output=(C::Collection, E::Span)>, E>
+    where
+        C: RegexSymbol,
+        E: Error<C>,
+{
+    struct Regex<C, E>(&'static str, Rc<DFA>, RefCell<Cache>, PhantomData<(C, E)>);
+
+    impl<C, E> Pattern<E> for Regex<C, E>
+        where
+            C: RegexSymbol,
+            E: Error<C>,
+    {
+        type Input = C;
+        type Output = (C::Collection, E::Span);
+
+        fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+            let checkpoint = stream.checkpoint();
+            attempt(stream, |stream| {
+                match find(&self.1, &mut self.2.borrow_mut(), stream) {
+                    Some(len) => {
+                        let text = (*stream).take(len).map(|(_, c)| *c).collect();
+                        stream.take_slice(len);
+                        Ok(((text, stream.span_from(checkpoint)), Fail::none()))
+                    },
+                    None => match stream.next() {
+                        Some((idx, sym)) => Err(Fail::one(idx, E::unexpected_sym(sym, stream.span_from(checkpoint)).expected_label(self.0))),
+                        None => Err(Fail::one(stream.end_index(), E::unexpected_end(stream.end_span()).expected_label(self.0))),
+                    },
+                }
+            })
+        }
+
+        fn cloned(&self) -> Self where Self: Sized {
+            Self(self.0, self.1.clone(), RefCell::new(self.1.create_cache()), PhantomData)
+        }
+
+        fn describe(&self, _grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+            Node::Label(self.0)
+        }
+    }
+
+    let dfa = DFA::builder()
+        .configure(DFA::config().unicode_word_boundary(true))
+        .build(pattern)
+        .unwrap_or_else(|err| panic!("invalid regex {:?}: {}", pattern, err));
+    let cache = dfa.create_cache();
+    Parser::from_pat(Regex(pattern, Rc::new(dfa), RefCell::new(cache), PhantomData))
+}
@@ -0,0 +1,184 @@
+//! Event-driven parsing, which passes events to a `Sink` as it goes rather than building an output.
+//!
+//! Nodes marked with `.node(kind)` (see the `cst` module) produce start and end events, the inputs between events
+//! are passed on as tokens, and `.emitted()` passes a parser's output on as a value. A parser whose outputs are all
+//! emitted can produce `()` (or vectors of `()`, which take no memory), so large inputs can be processed without
+//! building anything.
+//!
+//! ```ignore
+//! let num = float().map(|s: String| s.parse::<f64>().unwrap()).emitted();
+//! let array = just('[').padding_for(num.separated_by(just(','))).padded_by(just(']')).node(Kind::Array);
+//!
+//! array.parse_sax(input.chars(), &mut sink)?;
+//! ```
+//!
+//! Input is parsed as a sequence of items, as with `Parser::parse_iter`, and the inputs of an item are kept until it has
+//! been parsed, so input that consists of many small items (such as one record per line) takes little memory however
+//! long it is. Events are passed on once they can no longer be rolled back, which is when no alternative (a branch of
+//! `or`, or an item of `repeated` and the like) that is still underway could fail back to before them. Until then they
+//! are held back, so the memory needed also depends on how much input an undecided alternative covers. An alternative
+//! that passes a `cut` can no longer fail back, so cutting after the start of a long construct lets its contents be
+//! passed on as they are parsed:
+//!
+//! ```ignore
+//! let array = just('[').cut().padding_for(value.separated_by(just(','))).padded_by(just(']')).node(Kind::Array);
+//! ```
+//!
+//! If parsing fails, the sink may already have seen the events that came before the error.
+
+use std::{
+    any::Any,
+    marker::PhantomData,
+};
+use crate::{
+    cst::{Kind, Event, EventLog},
+    iter::Window,
+    memo,
+    profile,
+    Parser,
+    Pattern,
+    Error,
+    Stream,
+    ParseResult,
+    grammar::{Grammar, Node},
+};
+
+/// Receives the events produced by `Parser::parse_sax`, in order.
+pub trait Sink<K, T> {
+    /// The type of the values passed to `value`. Emitted outputs of other types are ignored.
+    type Value: 'static;
+
+    fn start(&mut self, _kind: K) {}
+
+    fn end(&mut self, _kind: K) {}
+
+    /// A run of inputs between other events.
+    fn tokens(&mut self, _tokens: &[T]) {}
+
+    fn value(&mut self, _value: Self::Value) {}
+}
+
+impl<P, E> Parser<P, E> {
+    /// Passes the output of this parser to the sink as a value, if it is run with `parse_sax`, producing `()` instead.
+    pub fn emitted(self) -> Parser<impl Pattern<E, Input=P::Input, Output=()>, E>
+        where
+            P: Pattern<E>,
+            P::Output: 'static,
+            E: Error<P::Input>,
+    {
+        struct Emitted<A, E>(A, PhantomData<E>);
+
+        impl<A, E> Pattern<E> for Emitted<A, E>
+            where
+                A: Pattern<E>,
+                A::Output: 'static,
+                E: Error<A::Input>,
+        {
+            type Input = A::Input;
+            type Output = ();
+
+            fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+                let (out, fail) = self.0.parse(stream)?;
+                stream.emit(Event::Value(Box::new(out) as Box<dyn Any>));
+                Ok(((), fail))
+            }
+
+            fn cloned(&self) -> Self where Self: Sized {
+                Self(self.0.cloned(), PhantomData)
+            }
+
+            fn describe(&self, grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+                self.0.describe(grammar)
+            }
+        }
+
+        Parser::from_pat(Emitted(self.pat, PhantomData))
+    }
+
+    /// Parses `inputs` as a sequence of items, as `parse_iter` does, passing events to `sink` as it goes. See the `sax`
+    /// module.
+    ///
+    /// Inputs are pulled from `inputs` only as they are needed, and dropped once the item they belong to has been passed
+    /// on, so the whole input is never held at once. Parsing stops at the first error.
+    pub fn parse_sax<I, K, S>(&self, inputs: I, sink: &mut S) -> Result<Vec<P::Output>, Vec<E>>
+        where
+            P: Pattern<E>,
+            I: IntoIterator<Item=P::Input>,
+            K: Kind,
+            S: Sink<K, P::Input>,
+            E: Error<P::Input>,
+    {
+        let mut window = Window::new(inputs.into_iter());
+        let mut outs = Vec::new();
+        let mut kinds = Vec::new();
+        while !window.items().is_empty() || window.refill() {
+            // The number of events of this item already passed on, and the position that tokens have been passed up to
+            let mut passed = 0;
+            let mut pos = 0;
+
+            // An item is parsed again with more input whenever it looks past the inputs pulled so far, skipping the
+            // events that were passed on before it did
+            let (result, consumed) = profile::session(|| loop {
+                let (result, consumed, held) = {
+                    let items = window.items();
+                    let mut seen = 0;
+                    let mut deliver = |event: Event, idx: usize| {
+                        seen += 1;
+                        if seen <= passed {
+                            return;
+                        }
+                        passed = seen;
+                        if idx > pos {
+                            sink.tokens(&items[pos..idx]);
+                            pos = idx;
+                        }
+                        match event {
+                            Event::Start(kind) => {
+                                let kind = K::from_raw(kind);
+                                kinds.push(kind);
+                                sink.start(kind);
+                            },
+                            Event::Finish => sink.end(kinds.pop().unwrap()),
+                            Event::Value(value) => if let Ok(value) = value.downcast::<S::Value>() {
+                                sink.value(*value);
+                            },
+                        }
+                    };
+
+                    let events = EventLog::with_sink(&mut deliver, Some(items.len()).filter(|_| window.has_more()));
+                    let mut stream = Stream::with_events(items, &events);
+                    let start = stream;
+                    let result = memo::session(|| {
+                        stream = start;
+                        self.pat.parse(&mut stream)
+                    });
+                    if result.is_ok() {
+                        events.flush(stream.events_len());
+                    }
+                    (result, stream.checkpoint() as usize, events.is_held())
+                };
+                // Once the source has run out, nothing is held back
+                if !held {
+                    break (result, consumed);
+                }
+                window.refill();
+            });
+
+            match result {
+                Ok((out, _)) => {
+                    if consumed > pos {
+                        sink.tokens(&window.items()[pos..consumed]);
+                    }
+                    outs.push(out);
+                    window.consume(consumed);
+                    // An item that consumes nothing would be parsed forever
+                    if consumed == 0 {
+                        break;
+                    }
+                },
+                Err(fail) => return Err(fail.collect()),
+            }
+        }
+        Ok(outs)
+    }
+}
@@ -0,0 +1,168 @@
+//! Source files, and the conversion of spans within them to lines and columns.
+//!
+//! ```ignore
+//! let mut sources = SourceMap::new();
+//! let file = sources.add("main.txt", "let x = 1;\nlet y = ?;");
+//!
+//! if let Err(errs) = parser.parse_file(&sources, file) {
+//!     let loc = sources.location(errs[0].span().unwrap().start, file, Column::Utf16);
+//!     println!("{}:{}:{}", sources.name(file), loc.line + 1, loc.column + 1);
+//! }
+//! ```
+//!
+//! Offsets within files are always byte offsets.
+
+use std::ops::Range;
+use crate::{
+    span::{Span, Join},
+    Parser,
+    Pattern,
+    Error,
+    Stream,
+};
+
+/// Identifies a file within a `SourceMap`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FileId(u32);
+
+/// A span of bytes within a particular file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FileSpan {
+    pub file: FileId,
+    pub range: Range<usize>,
+}
+
+impl<T> Span<T> for FileSpan {
+    fn none() -> Self {
+        Self::default()
+    }
+
+    fn single(index: usize, _sym: &T) -> Self {
+        Self { file: FileId::default(), range: index..index + 1 }
+    }
+
+    fn group(_syms: &[T], range: Range<usize>) -> Self {
+        Self { file: FileId::default(), range }
+    }
+
+    fn in_file(self, file: FileId) -> Self {
+        Self { file, ..self }
+    }
+}
+
+impl Join for FileSpan {
+    /// Covers both spans, which must be in the same file.
+    fn join(self, other: Self) -> Self {
+        debug_assert_eq!(self.file, other.file, "cannot join spans in different files");
+        Self { file: self.file, range: self.range.start.min(other.range.start)..self.range.end.max(other.range.end) }
+    }
+}
+
+/// How columns are counted.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Column {
+    /// In `char`s (Unicode scalar values).
+    Chars,
+    /// In UTF-8 bytes.
+    Utf8,
+    /// In UTF-16 code units, as used by the Language Server Protocol.
+    Utf16,
+}
+
+/// A zero-based line and column.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+struct SourceFile {
+    name: String,
+    text: String,
+    // The byte offset at which each line starts
+    line_starts: Vec<usize>,
+}
+
+/// A collection of source files.
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, text: impl Into<String>) -> FileId {
+        let text = text.into();
+        let line_starts = Some(0)
+            .into_iter()
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        self.files.push(SourceFile { name: name.into(), text, line_starts });
+        FileId(self.files.len() as u32 - 1)
+    }
+
+    fn file(&self, file: FileId) -> &SourceFile {
+        &self.files[file.0 as usize]
+    }
+
+    pub fn name(&self, file: FileId) -> &str {
+        &self.file(file).name
+    }
+
+    pub fn text(&self, file: FileId) -> &str {
+        &self.file(file).text
+    }
+
+    /// The text covered by `span`.
+    pub fn slice(&self, span: &FileSpan) -> &str {
+        &self.text(span.file)[span.range.clone()]
+    }
+
+    /// The line and column of the byte at `offset` in `file`. Offsets past the end of the file are clamped to it.
+    ///
+    /// Finding the line takes logarithmic time in the number of lines.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `offset` is not on a `char` boundary.
+    pub fn location(&self, offset: usize, file: FileId, column: Column) -> Location {
+        let file = self.file(file);
+        let offset = offset.min(file.text.len());
+        let line = file.line_starts.partition_point(|start| *start <= offset) - 1;
+        let before = &file.text[file.line_starts[line]..offset];
+        let column = match column {
+            Column::Chars => before.chars().count(),
+            Column::Utf8 => before.len(),
+            Column::Utf16 => before.chars().map(char::len_utf16).sum(),
+        };
+        Location { line, column }
+    }
+
+    /// The locations of the start and end of `span`.
+    pub fn locate(&self, span: &FileSpan, column: Column) -> Range<Location> {
+        self.location(span.range.start, span.file, column)..self.location(span.range.end, span.file, column)
+    }
+}
+
+impl<P, E> Parser<P, E> {
+    /// Parses the text of `file`. Spans refer to byte offsets within it and, for span types that track files, to
+    /// `file` itself.
+    pub fn parse_file(&self, sources: &SourceMap, file: FileId) -> Result<P::Output, Vec<E>>
+        where
+            P: Pattern<E, Input=char>,
+            E: Error<char>,
+    {
+        let text = sources.text(file);
+        let (chars, spans): (Vec<_>, Vec<_>) = text
+            .char_indices()
+            .map(|(i, c)| (c, i..i + c.len_utf8()))
+            .unzip();
+        match self.parse_stream(&mut Stream::with_spans(&chars, &spans).in_file(file)) {
+            Ok((out, _)) => Ok(out),
+            Err(fail) => Err(fail.collect()),
+        }
+    }
+}
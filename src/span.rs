@@ -1,9 +1,13 @@
-use std::ops::Range;
+use std::ops::{Range, Deref, DerefMut};
+use crate::source::FileId;
 
-pub trait Span<T> {
+pub trait Span<T>: Sized {
     fn none() -> Self;
     fn single(index: usize, sym: &T) -> Self;
     fn group(syms: &[T], range: Range<usize>) -> Self;
+
+    /// This span, as referring to `file`. Spans that do not track files are left as they are.
+    fn in_file(self, _file: FileId) -> Self { self }
 }
 
 impl<T> Span<T> for Option<Range<usize>> {
@@ -19,3 +23,62 @@ impl<T> Span<T> for Option<Range<usize>> {
         Some(range)
     }
 }
+
+/// A span that can be extended to cover another.
+pub trait Join {
+    fn join(self, other: Self) -> Self;
+}
+
+impl Join for Option<Range<usize>> {
+    fn join(self, other: Self) -> Self {
+        match (self, other) {
+            (Some(a), Some(b)) => Some(a.start.min(b.start)..a.end.max(b.end)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+// Spanned
+
+/// A value along with the span of input it was parsed from. See `Parser::spanned`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Spanned<T, S = Option<Range<usize>>> {
+    pub node: T,
+    pub span: S,
+}
+
+impl<T, S> Spanned<T, S> {
+    pub fn new(node: T, span: S) -> Self {
+        Self { node, span }
+    }
+
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Spanned<U, S> {
+        Spanned { node: f(self.node), span: self.span }
+    }
+
+    /// The span covering both this and `other`.
+    pub fn join_span<U>(&self, other: &Spanned<U, S>) -> S
+        where S: Join + Clone
+    {
+        self.span.clone().join(other.span.clone())
+    }
+}
+
+impl<T, S> Deref for Spanned<T, S> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.node
+    }
+}
+
+impl<T, S> DerefMut for Spanned<T, S> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.node
+    }
+}
+
+/// The span covering all of `items`, or `None` if there are none.
+pub fn join_spans<'a, T: 'a, S: Join + Clone + 'a>(items: impl IntoIterator<Item=&'a Spanned<T, S>>) -> Option<S> {
+    items.into_iter().map(|item| item.span.clone()).reduce(Join::join)
+}
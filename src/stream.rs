@@ -1,38 +1,242 @@
+use std::ops::Range;
 use crate::{
     Index,
     span::Span,
+    cst::{Event, EventLog},
+    source::FileId,
+    ParseResult,
 };
 
 pub struct Stream<'a, T> {
     slice: &'a [T],
     index: usize,
+    // The source span of each item, if the items came from an earlier parsing stage
+    spans: Option<&'a [Range<usize>]>,
+    // Where the items start in the input, if they are only part of it
+    offset: usize,
+    // The log that events are written to, if a syntax tree is being built or events are being passed to a sink. Only
+    // the first `events_len` events belong to this stream: the rest were written by branches that were since abandoned.
+    events: Option<&'a EventLog<'a>>,
+    events_len: usize,
+    // The file that the items came from, if known
+    file: Option<FileId>,
+    // Whether a cut has been passed within the innermost alternative
+    cut: bool,
+    // Whether the items are the contents of a single item of another stream, such as a group of token trees
+    nested: bool,
 }
 
 impl<'a, T> Copy for Stream<'a, T> {}
 
 impl<'a, T> Clone for Stream<'a, T> {
     fn clone(&self) -> Self {
-        Self {
-            slice: self.slice,
-            index: self.index,
-        }
+        *self
     }
 }
 
 impl<'a, T> From<&'a [T]> for Stream<'a, T> {
     fn from(slice: &'a [T]) -> Self {
-        Self { slice, index: 0 }
+        Self { slice, index: 0, spans: None, offset: 0, events: None, events_len: 0, file: None, cut: false, nested: false }
     }
 }
 
 impl<'a, T> Stream<'a, T> {
+    /// A stream whose spans are given by `spans`, one per item, rather than by item indices.
+    pub(crate) fn with_spans(slice: &'a [T], spans: &'a [Range<usize>]) -> Self {
+        Self { spans: Some(spans), ..Self::from(slice) }
+    }
+
+    /// A stream of items that start `offset` items into the input.
+    pub(crate) fn with_offset(slice: &'a [T], offset: usize) -> Self {
+        Self { offset, ..Self::from(slice) }
+    }
+
+    /// A stream of the contents of a single item of another stream.
+    pub(crate) fn nested(self) -> Self {
+        Self { nested: true, ..self }
+    }
+
+    pub(crate) fn is_nested(&self) -> bool {
+        self.nested
+    }
+
     pub fn checkpoint(&self) -> Index {
         self.index as Index
     }
 
-    pub fn span_from<R: Span<T>>(&self, checkpoint: Index) -> R {
+    /// The position of the next item in the input, counting any items that came before this stream's first.
+    pub(crate) fn input_pos(&self) -> usize {
+        self.offset + self.index
+    }
+
+    pub(crate) fn peek(&self) -> Option<&'a T> {
+        let next = self.slice.get(self.index);
+        if next.is_none() {
+            self.reached_end();
+        }
+        next
+    }
+
+    #[cfg(feature = "regex")]
+    pub(crate) fn prev(&self) -> Option<&'a T> {
+        self.slice.get(self.index.checked_sub(1)?)
+    }
+
+    /// Consume and return the next `n` items, or nothing if fewer than `n` remain.
+    pub(crate) fn take_slice(&mut self, n: usize) -> Option<&'a [T]> {
+        match self.slice.get(self.index..self.index.saturating_add(n)) {
+            Some(slice) => {
+                self.index += n;
+                Some(slice)
+            },
+            None => {
+                self.reached_end();
+                None
+            },
+        }
+    }
+
+    /// A stream that ends `len` items from the current position, or nothing if fewer than `len` remain.
+    pub(crate) fn bounded(&self, len: usize) -> Option<Self> {
+        let slice = match self.slice.get(..self.index.saturating_add(len)) {
+            Some(slice) => slice,
+            None => {
+                self.reached_end();
+                return None;
+            },
+        };
+        Some(Self {
+            slice,
+            index: self.index,
+            spans: self.spans,
+            offset: self.offset,
+            events: self.events,
+            events_len: self.events_len,
+            file: self.file,
+            cut: self.cut,
+            nested: self.nested,
+        })
+    }
+
+    /// This stream, with spans that refer to `file`.
+    pub(crate) fn in_file(self, file: FileId) -> Self {
+        Self { file: Some(file), ..self }
+    }
+
+    /// A stream that writes events to `events`.
+    pub(crate) fn with_events(slice: &'a [T], events: &'a EventLog<'a>) -> Self {
+        Self { events: Some(events), ..Self::from(slice) }
+    }
+
+    /// Records an event at the current position, if a tree is being built or events are being passed to a sink.
+    pub(crate) fn emit(&mut self, event: Event) {
+        self.emit_at(event, self.index);
+    }
+
+    /// Records an event at the item index `index`, such as when replaying events recorded earlier.
+    pub(crate) fn emit_at(&mut self, event: Event, index: usize) {
+        if let Some(events) = self.events {
+            events.push(self.events_len, event, index);
+            self.events_len += 1;
+        }
+    }
+
+    /// Copies of the events written since this stream had `len` of them, leaving out emitted values.
+    pub(crate) fn events_since(&self, len: usize) -> Vec<(Event, usize)> {
+        self.events.map_or_else(Vec::new, |events| events.copy(len..self.events_len))
+    }
+
+    /// Whether events are being passed to a sink as they are settled, rather than kept.
+    pub(crate) fn is_streaming(&self) -> bool {
+        self.events.is_some_and(|events| events.has_sink())
+    }
+
+    // Notes that the parse has looked for items beyond the end of this stream, which may be followed by more (see
+    // `EventLog`)
+    fn reached_end(&self) {
+        if let (Some(events), false) = (self.events, self.nested) {
+            events.reached(self.offset + self.slice.len());
+        }
+    }
+
+    /// Runs `f` as one of several possibilities, holding back the events it writes from any sink until it is settled.
+    pub(crate) fn choice<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        match self.events {
+            Some(events) => {
+                events.enter_choice(self.events_len);
+                let r = f(self);
+                events.leave_choice(self.events_len);
+                r
+            },
+            None => f(self),
+        }
+    }
+
+    /// Marks the current alternative as committed, so that failing after this point skips any others.
+    pub(crate) fn cut(&mut self) {
+        self.cut = true;
+        if let Some(events) = self.events {
+            events.cut(self.events_len);
+        }
+    }
+
+    pub(crate) fn is_cut(&self) -> bool {
+        self.cut
+    }
+
+    /// Runs `f` as one of several alternatives (see `choice`). A cut within `f` lasts only until it returns, and if `f`
+    /// fails after passing one, the failure is committed.
+    pub(crate) fn alternative<O, E>(&mut self, f: impl FnOnce(&mut Self) -> ParseResult<O, E>) -> ParseResult<O, E> {
+        let cut = self.cut;
+        self.cut = false;
+        let result = self.choice(f).map_err(|fail| if self.cut { fail.commit() } else { fail });
+        self.cut = cut;
+        result
+    }
+
+    /// Keeps the events written to `other`, a stream derived from this one.
+    pub(crate) fn keep_events(&mut self, other: &Self) {
+        self.events_len = other.events_len;
+    }
+
+    /// The number of events that belong to this stream.
+    pub(crate) fn events_len(&self) -> usize {
+        self.events_len
+    }
+
+    /// The range of input covered since `checkpoint`, in terms of source spans if the stream has them.
+    pub(crate) fn range_from(&self, checkpoint: Index) -> Range<usize> {
         let checkpoint = checkpoint as usize;
-        Span::group(&self.slice[checkpoint..self.index], checkpoint..self.index)
+        match self.spans {
+            None => self.offset + checkpoint..self.offset + self.index,
+            Some(spans) if checkpoint < self.index => spans[checkpoint].start..spans[self.index - 1].end,
+            // An empty range sits at the start of the next item, or at the end of the last one
+            Some(spans) => {
+                let pos = match spans.get(self.index).filter(|_| self.index < self.slice.len()) {
+                    Some(next) => next.start,
+                    None => self.index.checked_sub(1).map_or(0, |i| spans[i].end),
+                };
+                pos..pos
+            },
+        }
+    }
+
+    /// The index of the end of the stream.
+    pub(crate) fn end_index(&self) -> Index {
+        self.slice.len() as Index
+    }
+
+    /// An empty span at the end of the stream.
+    pub(crate) fn end_span<R: Span<T>>(&self) -> R {
+        Self { index: self.slice.len(), ..*self }.span_from(self.end_index())
+    }
+
+    pub fn span_from<R: Span<T>>(&self, checkpoint: Index) -> R {
+        let span = R::group(&self.slice[checkpoint as usize..self.index], self.range_from(checkpoint));
+        match self.file {
+            Some(file) => span.in_file(file),
+            None => span,
+        }
     }
 }
 
@@ -45,6 +249,7 @@ impl<'a, T> Iterator for Stream<'a, T> {
             self.index += 1;
             Some((index as Index, next))
         } else {
+            self.reached_end();
             None
         }
     }
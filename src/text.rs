@@ -0,0 +1,319 @@
+use std::{
+    iter::FromIterator,
+    marker::PhantomData,
+};
+use crate::{
+    util::attempt,
+    grammar::{Grammar, Node},
+    Parser,
+    Pattern,
+    Error,
+    Stream,
+    ParseResult,
+    Fail,
+};
+
+/// A symbol that can be treated as text, such as `char` or `u8`.
+pub trait Character: Copy + PartialEq {
+    /// The type used to collect a sequence of these characters.
+    type Collection: FromIterator<Self>;
+
+    fn to_char(self) -> char;
+    /// The character for `c`, which is truncated if it does not fit.
+    fn from_char(c: char) -> Self;
+    fn is_whitespace(&self) -> bool;
+    fn is_ident_start(&self) -> bool;
+    fn is_ident_continue(&self) -> bool;
+
+    fn is_digit(&self, radix: u32) -> bool {
+        self.to_char().is_digit(radix)
+    }
+}
+
+impl Character for char {
+    type Collection = String;
+
+    fn to_char(self) -> char { self }
+    fn from_char(c: char) -> Self { c }
+    fn is_whitespace(&self) -> bool { char::is_whitespace(*self) }
+    fn is_ident_start(&self) -> bool { self.is_alphabetic() || *self == '_' }
+    fn is_ident_continue(&self) -> bool { self.is_alphanumeric() || *self == '_' }
+}
+
+impl Character for u8 {
+    type Collection = Vec<u8>;
+
+    fn to_char(self) -> char { self as char }
+    fn from_char(c: char) -> Self { c as u8 }
+    fn is_whitespace(&self) -> bool { self.is_ascii_whitespace() }
+    fn is_ident_start(&self) -> bool { self.is_ascii_alphabetic() || *self == b'_' }
+    fn is_ident_continue(&self) -> bool { self.is_ascii_alphanumeric() || *self == b'_' }
+}
+
+// Consumes characters while `f` holds, returning how many were consumed
+fn eat_while<C: Character>(stream: &mut Stream<C>, f: impl Fn(&C) -> bool) -> usize {
+    let mut n = 0;
+    while stream.peek().is_some_and(&f) {
+        stream.next();
+        n += 1;
+    }
+    n
+}
+
+fn eat<C: Character>(stream: &mut Stream<C>, c: char) -> bool {
+    match stream.peek() {
+        Some(sym) if sym.to_char() == c => {
+            stream.next();
+            true
+        },
+        _ => false,
+    }
+}
+
+// Scan
+
+// Runs `f` over the stream. If `f` gives up, it should leave the stream on the offending character so that an error
+// labelled with `label` can be produced for it.
+fn scan<C, O, E>(label: &'static str, f: impl Fn(&mut Stream<C>) -> Option<O> + Clone) -> Parser<impl Pattern<E, Input=C, Output=O>, E>
+    where
+        C: Character,
+        E: Error<C>,
+{
+    scan_literal(label, false, f)
+}
+
+// Like `scan`, but if `literal` is set then `f` matches exactly the text of `label`, which is how it is described
+fn scan_literal<C, O, E>(label: &'static str, literal: bool, f: impl Fn(&mut Stream<C>) -> Option<O> + Clone) -> Parser<impl Pattern<E, Input=C, Output=O>, E>
+    where
+        C: Character,
+        E: Error<C>,
+{
+    struct Scan<F, C, O, E>(&'static str, bool, F, PhantomData<(C, O, E)>);
+
+    impl<F, C, O, E> Pattern<E> for Scan<F, C, O, E>
+        where
+            C: Character,
+            F: Fn(&mut Stream<C>) -> Option<O> + Clone,
+            E: Error<C>,
+    {
+        type Input = C;
+        type Output = O;
+
+        fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+            let checkpoint = stream.checkpoint();
+            attempt(stream, |stream| {
+                match (self.2)(stream) {
+                    Some(out) => Ok((out, Fail::none())),
+                    None => match stream.next() {
+                        Some((idx, sym)) => Err(Fail::one(idx, E::unexpected_sym(sym, stream.span_from(checkpoint)).expected_label(self.0))),
+                        None => Err(Fail::one(stream.end_index(), E::unexpected_end(stream.end_span()).expected_label(self.0))),
+                    },
+                }
+            })
+        }
+
+        fn cloned(&self) -> Self where Self: Sized {
+            Self(self.0, self.1, self.2.clone(), PhantomData)
+        }
+
+        fn describe(&self, _grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+            if self.1 {
+                Node::Seq(self.0.chars().map(|c| C::from_char(c).into()).collect())
+            } else {
+                Node::Label(self.0)
+            }
+        }
+    }
+
+    Parser::from_pat(Scan(label, literal, f, PhantomData))
+}
+
+fn collect<C: Character>(stream: Stream<C>, len: usize) -> C::Collection {
+    stream.take(len).map(|(_, c)| *c).collect()
+}
+
+// Whitespace
+
+/// Skips zero or more whitespace characters.
+pub fn whitespace<C, E>() -> Parser<impl Pattern<E, Input=C, Output=()>, E>
+    where
+        C: Character,
+        E: Error<C>,
+{
+    scan("whitespace", |stream| {
+        eat_while(stream, C::is_whitespace);
+        Some(())
+    })
+}
+
+// Newline
+
+/// Parses a single `\n` or `\r\n` line ending.
+pub fn newline<C, E>() -> Parser<impl Pattern<E, Input=C, Output=()>, E>
+    where
+        C: Character,
+        E: Error<C>,
+{
+    scan("newline", |stream| {
+        eat(stream, '\r');
+        if eat(stream, '\n') { Some(()) } else { None }
+    })
+}
+
+// Ident
+
+/// Parses a C-style identifier: a letter or underscore followed by any number of letters, digits or underscores.
+pub fn ident<C, E>() -> Parser<impl Pattern<E, Input=C, Output=C::Collection>, E>
+    where
+        C: Character,
+        E: Error<C>,
+{
+    scan("identifier", |stream: &mut Stream<C>| {
+        let start = *stream;
+        match stream.peek() {
+            Some(c) if c.is_ident_start() => Some(collect(start, eat_while(stream, C::is_ident_continue))),
+            _ => None,
+        }
+    })
+}
+
+// Keyword
+
+/// Parses an identifier that is exactly `keyword`.
+///
+/// Unlike `seq`, this will not accept a prefix of a longer identifier: `keyword("if")` rejects `iffy`.
+pub fn keyword<C, E>(keyword: &'static str) -> Parser<impl Pattern<E, Input=C, Output=()>, E>
+    where
+        C: Character,
+        E: Error<C>,
+{
+    scan_literal(keyword, true, move |stream: &mut Stream<C>| {
+        let start = *stream;
+        let len = match stream.peek() {
+            Some(c) if c.is_ident_start() => eat_while(stream, C::is_ident_continue),
+            _ => return None,
+        };
+        if start.take(len).map(|(_, c)| c.to_char()).eq(keyword.chars()) {
+            Some(())
+        } else {
+            *stream = start;
+            None
+        }
+    })
+}
+
+// Int
+
+/// Parses one or more digits in the given radix, returning them unconverted. See `uint` for a parser that converts
+/// them.
+pub fn int<C, E>(radix: u32) -> Parser<impl Pattern<E, Input=C, Output=C::Collection>, E>
+    where
+        C: Character,
+        E: Error<C>,
+{
+    scan("integer", move |stream: &mut Stream<C>| {
+        let start = *stream;
+        match eat_while(stream, |c: &C| c.is_digit(radix)) {
+            0 => None,
+            len => Some(collect(start, len)),
+        }
+    })
+}
+
+// Uint
+
+/// Parses an unsigned integer and converts it to a `u64`. Digits are decimal unless prefixed with `0x`, `0o` or `0b`.
+///
+/// A prefix without digits after it is taken to be a lone `0`. Values that do not fit in a `u64` are rejected.
+pub fn uint<C, E>() -> Parser<impl Pattern<E, Input=C, Output=u64>, E>
+    where
+        C: Character,
+        E: Error<C>,
+{
+    scan("integer", |stream: &mut Stream<C>| {
+        let start = *stream;
+        let radix = if !eat(stream, '0') {
+            10
+        } else if eat(stream, 'x') || eat(stream, 'X') {
+            16
+        } else if eat(stream, 'o') || eat(stream, 'O') {
+            8
+        } else if eat(stream, 'b') || eat(stream, 'B') {
+            2
+        } else {
+            *stream = start;
+            10
+        };
+
+        let digits = *stream;
+        let len = eat_while(stream, |c: &C| c.is_digit(radix));
+        if len == 0 {
+            return if radix == 10 {
+                None
+            } else {
+                *stream = start;
+                stream.next();
+                Some(0)
+            };
+        }
+
+        let value = digits.take(len).try_fold(0u64, |n, (_, c)| {
+            n.checked_mul(radix as u64)?.checked_add(c.to_char().to_digit(radix)? as u64)
+        });
+        if value.is_none() {
+            *stream = start;
+        }
+        value
+    })
+}
+
+// Float
+
+/// Parses a decimal number with an optional fractional part and exponent (`1`, `1.5`, `1e-3`, `2.5E+10`), returning
+/// it unconverted.
+pub fn float<C, E>() -> Parser<impl Pattern<E, Input=C, Output=C::Collection>, E>
+    where
+        C: Character,
+        E: Error<C>,
+{
+    scan("float", |stream| {
+        let start = *stream;
+        let digits = |stream: &mut Stream<C>| eat_while(stream, |c| c.is_digit(10));
+
+        let mut len = match digits(stream) {
+            0 => return None,
+            len => len,
+        };
+
+        let before_frac = *stream;
+        if eat(stream, '.') {
+            match digits(stream) {
+                0 => *stream = before_frac,
+                n => len += 1 + n,
+            }
+        }
+
+        let before_exp = *stream;
+        if eat(stream, 'e') || eat(stream, 'E') {
+            let sign = (eat(stream, '+') || eat(stream, '-')) as usize;
+            match digits(stream) {
+                0 => *stream = before_exp,
+                n => len += 1 + sign + n,
+            }
+        }
+
+        Some(collect(start, len))
+    })
+}
+
+impl<P, E> Parser<P, E> {
+    /// Allows any amount of whitespace before and after this parser.
+    pub fn padded(self) -> Parser<impl Pattern<E, Input=P::Input, Output=P::Output>, E>
+        where
+            P: Pattern<E>,
+            P::Input: Character,
+            E: Error<P::Input>,
+    {
+        whitespace().padding_for(self).padded_by(whitespace())
+    }
+}
@@ -0,0 +1,131 @@
+//! Tracing of parser execution, for debugging grammars.
+//!
+//! Give a parser a name with `Parser::debug`. When the `trace` feature is enabled, every run of that parser is
+//! logged with the position it started at and how it ended, indented beneath the runs of other traced parsers that
+//! it happened within. Positions are input indices.
+//!
+//! ```text
+//! list @0
+//!   item @0
+//!   item ok 0..1
+//!   item @2
+//!   item err at 2: [...]
+//! list ok 0..2 (furthest failure at 2: [...])
+//! ```
+//!
+//! Logs are written to stderr, unless collected with `capture`. Without the `trace` feature, `debug` does nothing, so
+//! it may be left in place.
+
+use std::{
+    fmt::Debug,
+    marker::PhantomData,
+};
+use crate::{
+    Parser,
+    Pattern,
+    Error,
+    Stream,
+    ParseResult,
+    grammar::{Grammar, Node},
+};
+
+#[cfg(feature = "trace")]
+mod log {
+    use std::{
+        cell::{Cell, RefCell},
+        fmt::Debug,
+    };
+    use crate::Fail;
+
+    thread_local! {
+        static DEPTH: Cell<usize> = const { Cell::new(0) };
+        static CAPTURED: RefCell<Option<String>> = const { RefCell::new(None) };
+    }
+
+    pub fn line(line: String) {
+        let line = format!("{}{}", "  ".repeat(DEPTH.with(Cell::get)), line);
+        CAPTURED.with(|captured| match captured.borrow_mut().as_mut() {
+            Some(captured) => { *captured += &line; captured.push('\n'); },
+            None => eprintln!("{}", line),
+        });
+    }
+
+    pub fn nested<R>(f: impl FnOnce() -> R) -> R {
+        DEPTH.with(|depth| depth.set(depth.get() + 1));
+        let r = f();
+        DEPTH.with(|depth| depth.set(depth.get() - 1));
+        r
+    }
+
+    pub fn failure<E: Debug>(fail: &Fail<E>) -> Option<String> {
+        match fail {
+            Fail::None => None,
+            Fail::One(pos, err) => Some(format!("at {}: [{:?}]", pos.0, err)),
+            Fail::Group(pos, errs) => Some(format!("at {}: {:?}", pos.0, errs)),
+            Fail::Committed(pos, errs) => Some(format!("committed at {}: {:?}", pos.0, errs)),
+        }
+    }
+
+    pub fn capture<R>(f: impl FnOnce() -> R) -> (R, String) {
+        let outer = CAPTURED.with(|captured| captured.replace(Some(String::new())));
+        let r = f();
+        let log = CAPTURED.with(|captured| captured.replace(outer)).unwrap_or_default();
+        (r, log)
+    }
+}
+
+/// Runs `f`, returning what traced parsers logged during it instead of writing it to stderr.
+#[cfg(feature = "trace")]
+pub fn capture<R>(f: impl FnOnce() -> R) -> (R, String) {
+    log::capture(f)
+}
+
+impl<P, E> Parser<P, E> {
+    /// Logs each run of this parser under `name`, if the `trace` feature is enabled. See the `trace` module.
+    pub fn debug(self, name: &'static str) -> Parser<impl Pattern<E, Input=P::Input, Output=P::Output>, E>
+        where
+            P: Pattern<E>,
+            E: Error<P::Input> + Debug,
+    {
+        struct Traced<A, E>(&'static str, A, PhantomData<E>);
+
+        impl<A, E> Pattern<E> for Traced<A, E>
+            where
+                A: Pattern<E>,
+                E: Error<A::Input> + Debug,
+        {
+            type Input = A::Input;
+            type Output = A::Output;
+
+            #[cfg(not(feature = "trace"))]
+            fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+                self.1.parse(stream)
+            }
+
+            #[cfg(feature = "trace")]
+            fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+                let checkpoint = stream.checkpoint();
+                log::line(format!("{} @{}", self.0, checkpoint));
+                let result = log::nested(|| self.1.parse(stream));
+                match &result {
+                    Ok((_, fail)) => log::line(match log::failure(fail) {
+                        Some(fail) => format!("{} ok {}..{} (furthest failure {})", self.0, checkpoint, stream.checkpoint(), fail),
+                        None => format!("{} ok {}..{}", self.0, checkpoint, stream.checkpoint()),
+                    }),
+                    Err(fail) => log::line(format!("{} err {}", self.0, log::failure(fail).unwrap_or_default())),
+                }
+                result
+            }
+
+            fn cloned(&self) -> Self where Self: Sized {
+                Self(self.0, self.1.cloned(), PhantomData)
+            }
+
+            fn describe(&self, grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+                self.1.describe(grammar)
+            }
+        }
+
+        Parser::from_pat(Traced(name, self.pat, PhantomData))
+    }
+}
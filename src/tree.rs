@@ -0,0 +1,150 @@
+//! Token trees: tokens grouped by matching delimiters such as `()`, `[]` and `{}`.
+//!
+//! Trees are built from a lexer's tokens with `build`, then parsed with `Parser::parse_spanned`. The `group`
+//! primitive enters a delimited group and parses its contents.
+
+use std::{
+    marker::PhantomData,
+    ops::Range,
+};
+use crate::{
+    util::attempt,
+    grammar::{Grammar, Node},
+    Parser,
+    Pattern,
+    Error,
+    Stream,
+    ParseResult,
+    Fail,
+};
+
+type Trees<T> = Vec<(TokenTree<T>, Range<usize>)>;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TokenTree<T> {
+    Token(T),
+    Group(Delimited<T>),
+}
+
+/// The contents of a group, along with its delimiters.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Delimited<T> {
+    pub open: T,
+    pub close: T,
+    pub trees: Vec<TokenTree<T>>,
+    /// The source span of each tree within the group.
+    pub spans: Vec<Range<usize>>,
+}
+
+/// A delimiter without a matching partner.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Unbalanced<T> {
+    /// The innermost open delimiter, or `None` if no group was open.
+    pub open: Option<(T, Range<usize>)>,
+    /// The delimiter found instead of the one that closes `open`, or `None` if the input ended first.
+    pub close: Option<(T, Range<usize>)>,
+}
+
+/// Groups `tokens` into trees, given pairs of opening and closing delimiters.
+///
+/// The span of a group covers both of its delimiters.
+pub fn build<T, I>(tokens: I, delims: &[(T, T)]) -> Result<Trees<T>, Unbalanced<T>>
+    where
+        T: PartialEq + Clone,
+        I: IntoIterator<Item=(T, Range<usize>)>,
+{
+    // The open groups, each with its opening delimiter and span, and its contents
+    let mut stack: Vec<(Range<usize>, Delimited<T>)> = Vec::new();
+    let mut trees = Vec::new();
+    let mut spans = Vec::new();
+
+    for (tok, span) in tokens {
+        let tree = if let Some((_, close)) = delims.iter().find(|(open, _)| *open == tok) {
+            let group = Delimited { open: tok, close: close.clone(), trees: Vec::new(), spans: Vec::new() };
+            stack.push((span, group));
+            continue;
+        } else if delims.iter().any(|(_, close)| *close == tok) {
+            match stack.pop() {
+                Some((open_span, group)) if group.close == tok => (TokenTree::Group(group), open_span.start..span.end),
+                open => return Err(Unbalanced {
+                    open: open.map(|(open_span, group)| (group.open, open_span)),
+                    close: Some((tok, span)),
+                }),
+            }
+        } else {
+            (TokenTree::Token(tok), span)
+        };
+
+        match stack.last_mut() {
+            Some((_, parent)) => {
+                parent.trees.push(tree.0);
+                parent.spans.push(tree.1);
+            },
+            None => {
+                trees.push(tree.0);
+                spans.push(tree.1);
+            },
+        }
+    }
+
+    match stack.pop() {
+        Some((open_span, group)) => Err(Unbalanced { open: Some((group.open, open_span)), close: None }),
+        None => Ok(trees.into_iter().zip(spans).collect()),
+    }
+}
+
+// Group
+
+/// Parses a group opened by `open`, whose entire contents must be parsed by `parser`.
+///
+/// Failures within the group are reported at the position of the group.
+pub fn group<T, P, E>(open: T, parser: Parser<P, E>) -> Parser<impl Pattern<E, Input=TokenTree<T>, Output=P::Output>, E>
+    where
+        T: PartialEq + Clone,
+        P: Pattern<E, Input=TokenTree<T>>,
+        E: Error<TokenTree<T>>,
+{
+    struct Group<T, P, E>(T, P, PhantomData<E>);
+
+    impl<T, P, E> Pattern<E> for Group<T, P, E>
+        where
+            T: PartialEq + Clone,
+            P: Pattern<E, Input=TokenTree<T>>,
+            E: Error<TokenTree<T>>,
+    {
+        type Input = TokenTree<T>;
+        type Output = P::Output;
+
+        fn parse(&self, stream: &mut Stream<Self::Input>) -> ParseResult<Self::Output, E> {
+            let checkpoint = stream.checkpoint();
+            attempt(stream, |stream| {
+                match stream.next() {
+                    Some((idx, TokenTree::Group(group))) if group.open == self.0 => {
+                        let mut inner = Stream::with_spans(&group.trees, &group.spans).nested();
+                        let (out, fail) = self.1.parse(&mut inner).map_err(|fail| fail.at(idx))?;
+                        let inner_checkpoint = inner.checkpoint();
+                        match inner.next() {
+                            Some((_, sym)) => Err(Fail::one(idx, E::expected_end(sym, inner.span_from(inner_checkpoint))).max(fail.at(idx))),
+                            None => Ok((out, fail.at(idx))),
+                        }
+                    },
+                    Some((idx, sym)) => Err(Fail::one(idx, E::unexpected_sym(sym, stream.span_from(checkpoint)))),
+                    None => Err(Fail::one(stream.end_index(), E::unexpected_end(stream.end_span()))),
+                }
+            })
+        }
+
+        fn cloned(&self) -> Self where Self: Sized {
+            Self(self.0.clone(), self.1.cloned(), PhantomData)
+        }
+
+        // The closing delimiter is whichever one matches `open`
+        fn describe(&self, grammar: &mut Grammar<E::Thing>) -> Node<E::Thing> {
+            Node::Just(TokenTree::Token(self.0.clone()).into())
+                .then(self.1.describe(grammar))
+                .then(Node::Label("closing delimiter"))
+        }
+    }
+
+    Parser::from_pat(Group(open, parser.pat, PhantomData))
+}
@@ -1,10 +1,12 @@
-use crate::Stream;
+use crate::{Stream, Fail};
 
-pub fn attempt<'a, T, R, F, E>(tokens: &mut Stream<T>, f: F) -> Result<R, E>
-    where F: FnOnce(&mut Stream<T>) -> Result<R, E>,
+/// Runs `f` on a copy of `tokens`, keeping its progress only if it succeeds. A failure after `f` passes a cut is
+/// committed.
+pub fn attempt<T, R, F, E>(tokens: &mut Stream<T>, f: F) -> Result<R, Fail<E>>
+    where F: FnOnce(&mut Stream<T>) -> Result<R, Fail<E>>,
 {
-    let mut tokens2 = tokens.clone();
-    let tok = f(&mut tokens2)?;
+    let mut tokens2 = *tokens;
+    let tok = f(&mut tokens2).map_err(|fail| if tokens2.is_cut() && !tokens.is_cut() { fail.commit() } else { fail })?;
     *tokens = tokens2;
     Ok(tok)
 }
@@ -1,4 +1,7 @@
-use parze::prelude::*;
+use parze::{
+    prelude::*,
+    error::SimpleError,
+};
 
 #[test]
 fn a() {
@@ -114,3 +117,854 @@ fn bf() {
         ]),
     );
 }
+
+#[test]
+fn expected() {
+    // OneOf
+
+    let p = one_of::<_, SimpleError<u8>>(b"abc");
+
+    assert_eq!(p.parse(b"b".to_vec()).ok(), Some(b'b'));
+    let errs = p.parse(b"d".to_vec()).unwrap_err();
+    assert_eq!(errs[0].found(), Some(&b'd'));
+    assert_eq!(errs[0].expected_set().unwrap().len(), 3);
+    assert!(errs[0].expected_set().unwrap().contains(&b'c'));
+
+    // NoneOf
+
+    let p = none_of::<_, SimpleError<char>>("\\\"".chars());
+
+    assert_eq!(p.parse("a".chars()).ok(), Some('a'));
+    assert!(p.parse("\"".chars()).is_err());
+
+    // Filter
+
+    let p = filter::<_, SimpleError<char>>("digit", |c: &char| c.is_ascii_digit());
+
+    assert_eq!(p.parse("7".chars()).ok(), Some('7'));
+    let errs = p.parse("x".chars()).unwrap_err();
+    assert!(errs[0].labels().contains("digit"));
+
+    // End of input
+
+    let p = just::<_, _, SimpleError<char>>('a').padding_for(just('b').or(just('c')).or(seq("cd".chars()).to('d')));
+    let errs = p.parse("a".chars()).unwrap_err();
+    assert_eq!(errs.len(), 1);
+    assert_eq!(errs[0].found(), None);
+    assert_eq!(errs[0].span(), Some(1..1));
+    assert_eq!(errs[0].expected_set().unwrap().len(), 2);
+    let errs = p.parse_spanned(vec![('a', 4..7)]).unwrap_err();
+    assert_eq!(errs[0].span(), Some(7..7));
+
+    // Select
+
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    enum Token { Ident(String), Num(i64), Comma }
+
+    let p = select! {
+        Token::Ident(s) => s,
+        Token::Num(n) if n >= 0 => n.to_string(),
+    }.separated_by(just::<_, _, SimpleError<_>>(Token::Comma));
+
+    assert_eq!(p.parse(vec![Token::Ident("a".into()), Token::Comma, Token::Num(3)]).ok(), Some(vec!["a".to_string(), "3".to_string()]));
+    let errs = p.padded_by(end()).parse(vec![Token::Num(-1)]).unwrap_err();
+    assert_eq!(errs[0].found(), Some(&Token::Num(-1)));
+    assert!(errs[0].labels().contains("Token::Ident(s)"));
+    assert!(errs[0].labels().contains("Token::Num(n)"));
+}
+
+#[test]
+fn text() {
+    use parze::text::*;
+
+    let p = ident::<char, DefaultError<_>>().padded().repeated();
+
+    assert_eq!(p.parse(" foo _bar\tbaz9 ".chars()), Ok(vec!["foo".to_string(), "_bar".to_string(), "baz9".to_string()]));
+
+    let p = keyword::<char, DefaultError<_>>("if").then(end());
+
+    assert!(p.parse("if".chars()).is_ok());
+    assert!(p.parse("iffy".chars()).is_err());
+
+    let p = int::<u8, DefaultError<_>>(16);
+
+    assert_eq!(p.parse(b"ff0z".to_vec()), Ok(b"ff0".to_vec()));
+    assert!(p.parse(b"z".to_vec()).is_err());
+
+    let p = uint::<char, DefaultError<_>>();
+
+    assert_eq!(p.parse("1234".chars()), Ok(1234));
+    assert_eq!(p.parse("0x1F".chars()), Ok(31));
+    assert_eq!(p.parse("0o17".chars()), Ok(15));
+    assert_eq!(p.parse("0b101".chars()), Ok(5));
+    assert_eq!(p.parse("0xg".chars()), Ok(0));
+    assert_eq!(p.parse("18446744073709551615".chars()), Ok(u64::MAX));
+    assert!(p.parse("18446744073709551616".chars()).is_err());
+
+    let p = float::<char, DefaultError<_>>();
+
+    assert_eq!(p.parse("1.5e-3".chars()), Ok("1.5e-3".to_string()));
+    assert_eq!(p.parse("42.x".chars()), Ok("42".to_string()));
+    assert_eq!(p.parse("7e".chars()), Ok("7".to_string()));
+
+    let p = newline::<char, DefaultError<_>>().repeated();
+
+    assert_eq!(p.parse("\n\r\n".chars()), Ok(vec![(), ()]));
+}
+
+#[test]
+fn binary() {
+    use parze::binary::*;
+
+    let p = u16_le::<DefaultError<_>>().then(i32_be()).then(f32_le());
+
+    assert_eq!(
+        p.parse([0x34, 0x12, 0xFF, 0xFF, 0xFF, 0xFE, 0x00, 0x00, 0x80, 0x3F].iter().copied()),
+        Ok(((0x1234, -2), 1.0)),
+    );
+    assert!(p.parse([0x34, 0x12, 0xFF].iter().copied()).is_err());
+
+    let p = uleb128::<DefaultError<_>>().then(sleb128());
+
+    assert_eq!(p.parse([0xE5, 0x8E, 0x26, 0x7F].iter().copied()), Ok((624485, -1)));
+
+    fn encode_sleb128(mut x: i64) -> Vec<u8> {
+        let mut out = Vec::new();
+        loop {
+            let byte = (x & 0x7F) as u8;
+            x >>= 7;
+            if (x == 0 && byte & 0x40 == 0) || (x == -1 && byte & 0x40 != 0) {
+                out.push(byte);
+                break out;
+            }
+            out.push(byte | 0x80);
+        }
+    }
+
+    assert_eq!(encode_sleb128(i64::MIN), vec![0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x7F]);
+    for x in [i64::MIN, i64::MIN + 1, -(1 << 62) - 1, -1, 0, i64::MAX] {
+        assert_eq!(sleb128::<DefaultError<_>>().parse(encode_sleb128(x)), Ok(x));
+    }
+    assert_eq!(uleb128::<DefaultError<_>>().parse(vec![0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]), Ok(u64::MAX));
+    assert!(uleb128::<DefaultError<_>>().parse(vec![0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x7F]).is_err());
+    assert!(sleb128::<DefaultError<_>>().parse(vec![0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01]).is_err());
+
+    let p = magic::<DefaultError<_>>(b"\x7FELF").padding_for(take(2));
+
+    assert_eq!(p.parse(b"\x7FELF\x01\x02\x03".to_vec()), Ok(4..6));
+    assert!(p.parse(b"\x7FELG\x01\x02".to_vec()).is_err());
+
+    let p = length_prefixed::<_, _, DefaultError<_>>(u8(), u8().repeated()).then(u8());
+
+    assert_eq!(p.parse([2, 10, 11, 12].iter().copied()), Ok((vec![10, 11], 12)));
+    assert!(p.parse([4, 10, 11].iter().copied()).is_err());
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn regex_match() {
+    let p = regex::<char, SimpleError<_>>(r"[0-9]+(\.[0-9]+)?").map(|(s, _)| s);
+
+    assert_eq!(p.parse("3.14xyz".chars()).ok(), Some("3.14".to_string()));
+    assert_eq!(p.parse("42.".chars()).ok(), Some("42".to_string()));
+    let errs = p.parse("x1".chars()).unwrap_err();
+    assert!(errs[0].labels().contains(r"[0-9]+(\.[0-9]+)?"));
+
+    let p = just::<_, _, SimpleError<_>>('λ').padding_for(regex(r"\w+"));
+
+    assert_eq!(p.parse("λüb c".chars()).ok(), Some(("üb".to_string(), Some(1..3))));
+
+    let p = just::<_, _, SimpleError<_>>('a').padding_for(regex(r"^b"));
+
+    assert!(p.parse("ab".chars()).is_err());
+
+    let p = regex::<u8, SimpleError<_>>(r"(?-u)\x00+");
+
+    assert_eq!(p.parse(b"\x00\x00\x01".to_vec()).ok(), Some((b"\x00\x00".to_vec(), Some(0..2))));
+}
+
+#[test]
+fn take_slices() {
+    let p = take_while::<_, DefaultError<char>>(|c| *c != '\n').map_with_span(|n, span| (n, span)).then(any());
+
+    assert_eq!(p.parse("abc\n".chars()), Ok(((3, Some(0..3)), '\n')));
+    assert_eq!(p.parse("\n".chars()), Ok(((0, Some(0..0)), '\n')));
+
+    let p = take_until::<_, DefaultError<char>>(seq("*/".chars())).then(seq("*/".chars()));
+
+    assert_eq!(p.parse("a * b */".chars()), Ok((6, vec!['*', '/'])));
+    assert!(p.parse("a * b *".chars()).is_err());
+
+    // A cut within `until` is kept once it succeeds, so a failure after `take_until` skips the other alternatives
+    let p = take_until::<_, DefaultError<char>>(just('*').cut()).padded_by(seq("*/".chars())).or(take(3));
+
+    assert_eq!(p.parse("ab*/".chars()), Ok(2));
+    assert!(p.parse("a*b".chars()).is_err());
+
+    let p = take::<_, DefaultError<char>>(2).map_with_span(|n, span| (n, span));
+
+    assert_eq!(p.parse("abc".chars()), Ok((2, Some(0..2))));
+    assert!(p.parse("a".chars()).is_err());
+}
+
+#[test]
+fn prefix() {
+    let header = seq::<_, _, SimpleError<u8>>(b"HDR".iter().copied()).padding_for(any());
+
+    assert_eq!(header.parse_prefix(b"HDR\x02rest".to_vec()).ok(), Some((2, 4)));
+    assert!(header.parse_prefix(b"HD".to_vec()).is_err());
+
+    let digits = one_of::<_, SimpleError<char>>("0123456789".chars()).repeated();
+
+    assert_eq!(digits.parse_complete("123".chars()).ok(), Some(vec!['1', '2', '3']));
+    let errs = digits.parse_complete("12a".chars()).unwrap_err();
+    assert_eq!(errs[0].found(), Some(&'a'));
+    assert_eq!(errs[0].span(), Some(2..3));
+    assert!(errs[0].expected_set().unwrap().contains(&'0'));
+}
+
+#[test]
+fn iter() {
+    use parze::text::*;
+
+    let record = ident::<char, SimpleError<_>>()
+        .padded_by(just('='))
+        .then(int(10))
+        .padded_by(just('\n'));
+    let src = "a=1\nb=x\nc=3\n";
+
+    let results = record.parse_iter(src.chars()).map(|r| r.ok()).collect::<Vec<_>>();
+    assert_eq!(results, vec![Some(("a".to_string(), "1".to_string())), None]);
+
+    let mut records = record.parse_iter(src.chars()).resync_after(|c| *c == '\n');
+    assert_eq!(records.next().unwrap().ok(), Some(("a".to_string(), "1".to_string())));
+    let errs = records.next().unwrap().unwrap_err();
+    assert_eq!(errs[0].found(), Some(&'x'));
+    assert_eq!(errs[0].span(), Some(6..7));
+    assert_eq!(records.position(), 8);
+    assert_eq!(records.next().unwrap().ok(), Some(("c".to_string(), "3".to_string())));
+    assert!(records.next().is_none());
+
+    // Inputs are only pulled as they are needed
+    let pulled = std::cell::Cell::new(0);
+    let src = "key=12345\n".repeat(10_000);
+    let mut records = record.parse_iter(src.chars().inspect(|_| pulled.set(pulled.get() + 1)));
+    assert!(records.next().unwrap().is_ok());
+    assert!(pulled.get() < 1_000);
+    assert_eq!(records.filter(Result::is_ok).count(), 9_999);
+}
+
+#[test]
+fn memo() {
+    use std::{rc::Rc, cell::Cell};
+    use parze::{text::*, memo::Memo};
+
+    let memo = Memo::new();
+    let parses = Rc::new(Cell::new(0));
+    let counter = parses.clone();
+    let item = ident::<char, DefaultError<_>>()
+        .map(move |s| { counter.set(counter.get() + 1); s })
+        .padded()
+        .memoized(&memo);
+    let p = item.repeated().padded_by(end());
+
+    let mut src = "alpha beta gamma delta".to_string();
+    assert_eq!(p.parse(src.chars()).unwrap().len(), 4);
+    assert_eq!(parses.get(), 4);
+    assert_eq!(memo.len(), 4);
+
+    // Unchanged input is entirely reused
+    p.parse(src.chars()).unwrap();
+    assert_eq!(parses.get(), 4);
+
+    // Only `beta` is parsed again, while `gamma` and `delta` are moved along
+    src.replace_range(7..8, "EE");
+    memo.edit(7..8, 2);
+    assert_eq!(p.parse(src.chars()).ok(), Some(vec!["alpha", "bEEta", "gamma", "delta"].into_iter().map(String::from).collect()));
+    assert_eq!(parses.get(), 5);
+
+    // A failed parse that reused outputs reports the same errors as one from scratch
+    let memo = Memo::new();
+    let p = one_of::<_, SimpleError<_>>("ab".chars()).repeated().memoized(&memo).then(just(';'));
+    let expected = |errs: Vec<SimpleError<char>>| {
+        let mut expected = errs[0].expected_set().unwrap().iter().copied().collect::<Vec<_>>();
+        expected.sort();
+        expected
+    };
+    assert_eq!(expected(p.parse("ab:".chars()).unwrap_err()), vec![';', 'a', 'b']);
+    assert_eq!(expected(p.parse("ab:".chars()).unwrap_err()), vec![';', 'a', 'b']);
+
+    // Within token tree groups, positions say nothing about where in the input a parser is
+    use parze::tree::*;
+    let memo = Memo::new();
+    let tokens = select! { TokenTree::Token(c) => c }.repeated().memoized(&memo);
+    let p = group::<_, _, DefaultError<_>>('(', tokens).repeated();
+    let lex = |s: &str| s.char_indices().filter(|(_, c)| *c != ' ').map(|(i, c)| (c, i..i + 1)).collect::<Vec<_>>();
+    let trees = build(lex("(a b) (c d)"), &[('(', ')')]).unwrap();
+    assert_eq!(p.parse_spanned(trees).ok(), Some(vec![vec!['a', 'b'], vec!['c', 'd']]));
+}
+
+#[test]
+fn grammar() {
+    use parze::{text::*, grammar::Node};
+
+    let expr = declare::<DefaultError<char>, char, ()>().named("expr");
+    let atom = int::<char, DefaultError<_>>(10).to(())
+        .or(just('(').padding_for(expr.link()).padded_by(just(')')));
+    let list = expr.define(atom.separated_by(just(',')).to(()));
+    let grammar = list.then(end()).grammar("file");
+
+    assert_eq!(grammar.rules().len(), 2);
+    assert_eq!(grammar.rules()[0].1, Node::Sequence(vec![Node::Rule("expr".to_string()), Node::End]));
+    assert_eq!(grammar.to_ebnf(), "file ::= expr <end> ;\nexpr ::= ((<integer> | '(' expr ')') (',' (<integer> | '(' expr ')'))*)? ;\n");
+
+    let svg = grammar.to_svg();
+    assert!(svg.starts_with("<svg") && svg.contains(">expr</text>") && svg.contains(">integer</text>"));
+
+    // Primitives that test symbols with arbitrary functions are described as far as their shape allows
+    let assign = keyword::<char, DefaultError<_>>("let")
+        .then(permit(|c: &char| c.is_alphabetic()))
+        .then(take(2))
+        .then(take_while(|c: &char| *c != ';'));
+    assert_eq!(assign.grammar("assign").to_ebnf(), "assign ::= ('l' 'e' 't') <any> <any> <any> <any>* ;\n");
+}
+
+#[cfg(feature = "trace")]
+#[test]
+fn trace() {
+    use parze::trace::capture;
+
+    let item = just::<_, _, DefaultError<char>>('a').debug("item");
+    let list = item.separated_by(just(',')).debug("list");
+    let (out, log) = capture(|| list.parse("a,b".chars()));
+
+    assert_eq!(out.ok(), Some(vec!['a']));
+    assert_eq!(log, "list @0\n  item @0\n  item ok 0..1\n  item @2\n  item err at 2: [EmptyError]\nlist ok 0..2 (furthest failure at 2: [EmptyError])\n");
+}
+
+#[test]
+fn profile() {
+    use parze::{text::*, profile::Profile};
+    use std::{cell::RefCell, rc::Rc};
+
+    let reports = Rc::new(RefCell::new(Vec::new()));
+    let profile = Profile::new().on_finish({
+        let reports = reports.clone();
+        move |profile| reports.borrow_mut().push(profile.report())
+    });
+    let name = ident::<char, DefaultError<_>>().profiled(&profile, "ident");
+    let call = name.clone().then(just('(')).to(()).profiled(&profile, "call");
+    let p = call.or(name.to(())).profiled(&profile, "expr");
+
+    p.parse("foo".chars()).unwrap();
+    p.parse("foo".chars()).unwrap();
+
+    let ident = profile.stats("ident").unwrap();
+    assert_eq!((ident.calls, ident.successes, ident.failures), (4, 4, 0));
+    // Each parse tries `call` first, so the first identifier is parsed twice
+    assert_eq!((ident.consumed, ident.discarded), (12, 6));
+    let call = profile.stats("call").unwrap();
+    assert_eq!((call.calls, call.failures, call.consumed, call.discarded), (2, 2, 0, 0));
+    assert_eq!(profile.stats("expr").unwrap().consumed, 6);
+    assert!(profile.report().lines().skip(1).all(|line| ["ident", "call", "expr"].iter().any(|name| line.starts_with(name))));
+    // The report is produced after each parse, without being asked for
+    assert_eq!(reports.borrow().len(), 2);
+    assert_eq!(reports.borrow().last(), Some(&profile.report()));
+
+    // Without a profiled top rule, the report still comes once per parse and backtracking is still noticed
+    reports.borrow_mut().clear();
+    let profile = Profile::new().on_finish({
+        let reports = reports.clone();
+        move |profile| reports.borrow_mut().push(profile.report())
+    });
+    let name = parze::text::ident::<char, DefaultError<_>>().profiled(&profile, "a");
+    let p = name.clone().then(just('(').profiled(&profile, "b")).to(()).or(name.to(()));
+
+    p.parse("foo".chars()).unwrap();
+
+    assert_eq!(reports.borrow().len(), 1);
+    assert!(reports.borrow()[0].lines().any(|line| line.starts_with("b ")));
+    assert_eq!(profile.stats("a").unwrap().discarded, 3);
+}
+
+#[test]
+fn generate() {
+    use parze::{text::*, generate::XorShift};
+
+    let list = recursive(|list| {
+        let item = int::<char, DefaultError<_>>(10).to(()).or(list.link()).padded();
+        just('[').padding_for(item.separated_by(just(','))).padded_by(just(']')).to(())
+    });
+    let gen = list.generator().with_text_labels();
+    let mut rng = XorShift::new(7);
+
+    // Without room to expand the rule again, lists cannot nest
+    assert!(gen.generate(&mut rng, 0).unwrap().iter().filter(|c| **c == '[').count() == 1);
+    for _ in 0..200 {
+        let input = gen.generate(&mut rng, 4).unwrap();
+        assert!(list.parse_complete(input.clone()).is_ok(), "{:?} was rejected", input.iter().collect::<String>());
+        let _ = list.parse(gen.mutate(&mut rng, &input));
+    }
+}
+
+#[test]
+fn bidi() {
+    use parze::{bidi, text::whitespace};
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum Value {
+        Bool(bool),
+        Num(u32),
+        List(Vec<Value>),
+    }
+
+    let value = bidi::recursive::<DefaultError<char>, _, _, _>(|value| {
+        let boolean = bidi::or(
+            bidi::to(bidi::seq("true".chars()), true),
+            bidi::to(bidi::seq("false".chars()), false),
+        );
+        let num = bidi::iso(
+            bidi::once_or_more(bidi::filter("digit", char::is_ascii_digit)),
+            |digits| digits.into_iter().collect::<String>().parse().unwrap(),
+            |n: &u32| Some(n.to_string().chars().collect()),
+        );
+        let sep = bidi::padded_by(bidi::just(','), bidi::skip(whitespace(), vec![' ']));
+        let list = bidi::padding_for(bidi::just('['), bidi::padded_by(bidi::separated_by(value, sep), bidi::just(']')));
+
+        bidi::or(
+            bidi::or(
+                bidi::iso(boolean, Value::Bool, |v| if let Value::Bool(b) = v { Some(*b) } else { None }),
+                bidi::iso(num, Value::Num, |v| if let Value::Num(n) = v { Some(*n) } else { None }),
+            ),
+            bidi::iso(list, Value::List, |v| if let Value::List(xs) = v { Some(xs.clone()) } else { None }),
+        )
+    });
+
+    let print = |v: &Value| value.print(v).map(|cs| cs.into_iter().collect::<String>());
+    let parsed = value.parse("[true,[1,   23],false]".chars()).ok().unwrap();
+
+    assert_eq!(parsed, Value::List(vec![Value::Bool(true), Value::List(vec![Value::Num(1), Value::Num(23)]), Value::Bool(false)]));
+    assert_eq!(print(&parsed).as_deref(), Some("[true, [1, 23], false]"));
+    assert_eq!(value.parse(print(&parsed).unwrap().chars()).ok(), Some(parsed));
+    assert_eq!(print(&Value::List(Vec::new())).as_deref(), Some("[]"));
+}
+
+#[test]
+fn spanned() {
+    use parze::{text::*, span::*};
+
+    let p = ident::<char, DefaultError<_>>().spanned().padded().repeated();
+    let idents = p.parse("ab  cde".chars()).unwrap();
+
+    assert_eq!(idents, vec![Spanned::new("ab".to_string(), Some(0..2)), Spanned::new("cde".to_string(), Some(4..7))]);
+    assert_eq!(idents[1].len(), 3);
+    assert_eq!(idents[0].join_span(&idents[1]), Some(0..7));
+    assert_eq!(join_spans(&idents), Some(Some(0..7)));
+    assert_eq!(join_spans(&idents[..0]), None);
+}
+
+#[test]
+fn source_map() {
+    use parze::{text::*, source::*, error::Error};
+
+    // An error that only records where it happened
+    struct At(FileSpan);
+
+    impl Error<char> for At {
+        type Span = FileSpan;
+        type Thing = char;
+        type Context = ();
+
+        fn unexpected_sym(_: &char, at: FileSpan) -> Self { At(at) }
+        fn unexpected_end(at: FileSpan) -> Self { At(at) }
+        fn expected_end(_: &char, at: FileSpan) -> Self { At(at) }
+    }
+
+    let mut sources = SourceMap::new();
+    let a = sources.add("a.txt", "x");
+    let b = sources.add("b.txt", "héllo\n  wörld");
+    let c = sources.add("c.txt", "héllo\n 𝄞 wörld!");
+
+    let p = ident::<_, At>().map_with_span(|_, span| span).padded().repeated().padded_by(end());
+    let spans = p.parse_file(&sources, b).ok().unwrap();
+    assert_eq!(spans, vec![FileSpan { file: b, range: 0..6 }, FileSpan { file: b, range: 9..15 }]);
+    assert_eq!(sources.slice(&spans[1]), "wörld");
+    assert_eq!(sources.name(a), "a.txt");
+
+    let p = none_of::<_, At>("!".chars()).repeated().padded_by(end());
+    let err = &p.parse_file(&sources, c).err().unwrap()[0].0;
+    assert_eq!(err.file, c);
+    assert_eq!(sources.location(err.range.start, c, Column::Chars), Location { line: 1, column: 8 });
+    assert_eq!(sources.location(err.range.start, c, Column::Utf16).column, 9);
+    assert_eq!(sources.location(err.range.start, c, Column::Utf8).column, 12);
+    assert_eq!(sources.locate(&spans[0], Column::Chars), Location { line: 0, column: 0 }..Location { line: 0, column: 5 });
+}
+
+#[test]
+fn lexed() {
+    use parze::{text::*, error::PipelineError};
+
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    enum Token { Ident(String), Num(u64), Plus }
+
+    let lexer = ident::<_, DefaultError<char>>().map(Token::Ident)
+        .or(int(10).map(|s: String| Token::Num(s.parse().unwrap())))
+        .or(just('+').to(Token::Plus))
+        .map_with_span(|tok, span| (tok, span))
+        .padded()
+        .repeated()
+        .padded_by(end());
+
+    let operand = select! { Token::Ident(s) => s, Token::Num(n) => n.to_string() }
+        .map_with_span(|s, span: Option<std::ops::Range<usize>>| (s, span));
+    let p = operand.clone().separated_by(just::<_, _, SimpleError<_>>(Token::Plus)).padded_by(end());
+
+    assert_eq!(
+        p.parse_lexed(&lexer, "ab + 42".chars()).ok(),
+        Some(vec![("ab".to_string(), Some(0..2)), ("42".to_string(), Some(5..7))]),
+    );
+    let p = operand.clone().then(just(Token::Plus).padding_for(operand)).map_with_span(|_, span| span);
+    assert_eq!(p.parse_spanned(vec![(Token::Num(1), 3..4), (Token::Plus, 5..6), (Token::Num(2), 7..9)]).ok(), Some(Some(3..9)));
+    let errs = p.parse_spanned(vec![(Token::Num(1), 3..4), (Token::Plus, 5..6), (Token::Plus, 7..8)]).unwrap_err();
+    assert_eq!(errs[0].span(), Some(7..8));
+
+    match p.parse_lexed(&lexer, "a + ?".chars()) {
+        Err(PipelineError::Lex(_)) => {},
+        _ => panic!("expected a lexing error"),
+    }
+}
+
+#[test]
+fn nested() {
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    enum Tok { Num(u64), Group(Vec<Tok>) }
+
+    fn num() -> Parser<impl Pattern<SimpleError<Tok>, Input=Tok, Output=u64>, SimpleError<Tok>> {
+        select! { Tok::Num(n) => n }
+    }
+
+    let group = |inner: Vec<Tok>| vec![(Tok::Num(1), 0..1), (Tok::Group(inner), 2..9), (Tok::Num(4), 10..11)];
+
+    // Nested symbols share the span of their group
+    let p = num().padding_for(nested_parse(|tok| match tok {
+        Tok::Group(toks) => Some((num().repeated().padded_by(end()).map_with_span(|ns, span| (ns, span)), toks)),
+        _ => None,
+    })).padded_by(num());
+
+    assert_eq!(p.parse_spanned(group(vec![Tok::Num(2), Tok::Num(3)])).ok(), Some((vec![2, 3], Some(2..9))));
+    let errs = p.parse_spanned(group(vec![Tok::Num(2), Tok::Group(vec![])])).unwrap_err();
+    assert_eq!(errs[0].found(), Some(&Tok::Group(vec![])));
+    assert_eq!(errs[0].span(), Some(2..9));
+
+    // Nested symbols with their own spans, one character wide after the opening delimiter
+    let p = num().padding_for(nested_parse_with(
+        |tok| match tok {
+            Tok::Group(toks) => Some((num().map_with_span(|n, span| (n, span)).repeated().padded_by(end()), toks)),
+            _ => None,
+        },
+        |outer, i| outer.start + 1 + i * 2..outer.start + 2 + i * 2,
+    ));
+
+    assert_eq!(p.parse_spanned(group(vec![Tok::Num(2), Tok::Num(3)])).ok(), Some(vec![(2, Some(3..4)), (3, Some(5..6))]));
+    let errs = p.padded_by(num()).parse_spanned(group(vec![Tok::Num(2), Tok::Group(vec![])])).unwrap_err();
+    assert_eq!(errs[0].span(), Some(5..6));
+
+    // Of two alternatives that fail within the same group, the one that got further into it is reported
+    let p = nested_parse(|tok| match tok {
+        Tok::Group(toks) => Some((num().then(num()).then(num()).to(()), toks)),
+        _ => None,
+    }).or(nested_parse(|tok| match tok {
+        Tok::Group(toks) => Some((num().then(select! { Tok::Group(_) => () }).to(()), toks)),
+        _ => None,
+    }));
+
+    let errs = p.parse(vec![Tok::Group(vec![Tok::Num(2), Tok::Num(3)])]).unwrap_err();
+    assert_eq!(errs.len(), 1);
+    assert_eq!(errs[0].found(), None);
+    assert!(!errs[0].labels().contains("Tok::Group(_)"));
+}
+
+#[test]
+fn token_trees() {
+    use parze::tree::*;
+
+    #[derive(Debug, PartialEq)]
+    enum Sexpr { Atom(char), List(Vec<Sexpr>) }
+
+    let lex = |s: &str| s.char_indices().filter(|(_, c)| *c != ' ').map(|(i, c)| (c, i..i + 1)).collect::<Vec<_>>();
+    let delims = [('(', ')'), ('[', ']')];
+
+    let sexpr = recursive::<SimpleError<_>, _, _, _>(|sexpr| {
+        let atom = select! { TokenTree::Token(c) if char::is_alphabetic(c) => Sexpr::Atom(c) };
+        atom.or(group('(', sexpr.link().repeated()).map(Sexpr::List))
+    });
+    let p = sexpr.map_with_span(|e, span| (e, span)).repeated().padded_by(end());
+
+    let trees = build(lex("(a (b c)) d"), &delims).unwrap();
+    assert_eq!(p.parse_spanned(trees).ok(), Some(vec![
+        (Sexpr::List(vec![Sexpr::Atom('a'), Sexpr::List(vec![Sexpr::Atom('b'), Sexpr::Atom('c')])]), Some(0..9)),
+        (Sexpr::Atom('d'), Some(10..11)),
+    ]));
+
+    // The contents of a group must be parsed entirely
+    let p = group::<_, _, SimpleError<_>>('(', select! { TokenTree::Token(c) => c }.repeated());
+    let errs = p.parse_spanned(build(lex("(a [b])"), &delims).unwrap()).unwrap_err();
+    assert_eq!(errs[0].span(), Some(3..6));
+
+    assert_eq!(build(lex("(a [b)]"), &delims), Err(Unbalanced { open: Some(('[', 3..4)), close: Some((')', 5..6)) }));
+    assert_eq!(build(lex("(a"), &delims), Err(Unbalanced { open: Some(('(', 0..1)), close: None }));
+    assert_eq!(build(lex("a]"), &delims), Err(Unbalanced { open: None, close: Some((']', 1..2)) }));
+}
+
+#[test]
+fn cst() {
+    use parze::{text::*, cst::*};
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    enum K { Root, Ws, Num, Call, Sum }
+
+    impl Kind for K {
+        fn to_raw(self) -> u16 { self as u16 }
+        fn from_raw(raw: u16) -> Self { [K::Root, K::Ws, K::Num, K::Call, K::Sum][raw as usize] }
+    }
+
+    let ws = whitespace::<_, DefaultError<char>>().node(K::Ws);
+    let num = int(10).node(K::Num).padded_by(ws.clone());
+    // The first branch emits a `Num` node before failing, which must not appear in the tree
+    let term = num.clone().then(just('(').padding_for(just(')'))).map(|(n, _)| n).node(K::Call).padded_by(ws.clone()).or(num);
+    let sum = term.clone().then(just('+').padding_for(ws.clone()).padding_for(term).repeated()).node(K::Sum);
+
+    let src = "1 + 2() + 3 ?";
+    let (out, tree) = sum.parse_cst(K::Root, src.chars()).unwrap();
+    assert_eq!(out, ("1".to_string(), vec!["2".to_string(), "3".to_string()]));
+    assert_eq!(tree.collect::<String>(), src);
+    assert_eq!(tree.len(), src.len());
+
+    let kinds = |node: &GreenNode<K, char>| node.children().iter().map(|child| match child {
+        GreenElement::Node(node) => Some(node.kind()),
+        GreenElement::Token(_) => None,
+    }).collect::<Vec<_>>();
+    let sum = match &tree.children()[0] {
+        GreenElement::Node(node) => node,
+        _ => panic!(),
+    };
+    assert_eq!(kinds(&tree), vec![Some(K::Sum), None]);
+    assert_eq!(kinds(sum), vec![Some(K::Num), Some(K::Ws), None, Some(K::Ws), Some(K::Call), Some(K::Ws), None, Some(K::Ws), Some(K::Num), Some(K::Ws)]);
+
+    // Reused outputs bring their nodes along
+    let memo = parze::memo::Memo::new();
+    let num = int::<_, DefaultError<char>>(10).node(K::Num).memoized(&memo);
+    let sum = num.separated_by(just('+').node(K::Ws)).node(K::Sum);
+    let (_, first) = sum.parse_cst(K::Root, "1+23+4".chars()).unwrap();
+    assert_eq!(memo.len(), 3);
+    let (_, second) = sum.parse_cst(K::Root, "1+23+4".chars()).unwrap();
+    assert_eq!(first, second);
+}
+
+#[test]
+fn cut() {
+    use parze::text::*;
+
+    let call = seq::<_, _, SimpleError<_>>("if".chars()).cut().padding_for(just('(')).padding_for(ident()).padded_by(just(')'));
+    let p = call.map(Some).or(ident().to(None));
+    let chars = |s: &str| s.chars().collect::<Vec<_>>();
+
+    assert_eq!(p.parse(chars("if(x)")).ok(), Some(Some("x".to_string())));
+    assert_eq!(p.parse(chars("fi")).ok(), Some(None));
+    // Once past `if`, `ident` is not tried and the error is the one after the cut
+    let errs = p.parse(chars("if x")).unwrap_err();
+    assert_eq!(errs.len(), 1);
+    assert_eq!(errs[0].found(), Some(&' '));
+    assert!(errs[0].expected_set().unwrap().contains(&'('));
+    assert!(errs[0].labels().is_empty());
+
+    // Committed failures pass through repetitions, but a cut lasts only as long as its alternative
+    let list = p.clone().separated_by(just(','));
+    assert_eq!(list.parse(chars("a,if(x)")).map(|xs| xs.len()).ok(), Some(2));
+    assert!(list.parse(chars("a,if x")).is_err());
+    assert_eq!(p.padded_by(just('!')).or_not().parse(chars("if(x)?")).ok(), Some(None));
+}
+
+#[test]
+fn sax() {
+    use parze::{text::*, cst::Kind, sax::Sink};
+    use std::{cell::Cell, rc::Rc};
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    enum K { List, Neg }
+
+    impl Kind for K {
+        fn to_raw(self) -> u16 { self as u16 }
+        fn from_raw(raw: u16) -> Self { [K::List, K::Neg][raw as usize] }
+    }
+
+    #[derive(Default)]
+    struct Log(Vec<String>);
+
+    impl Sink<K, char> for Log {
+        type Value = u32;
+
+        fn start(&mut self, kind: K) { self.0.push(format!("start {:?}", kind)) }
+        fn end(&mut self, kind: K) { self.0.push(format!("end {:?}", kind)) }
+        fn tokens(&mut self, tokens: &[char]) { self.0.push(tokens.iter().collect()) }
+        fn value(&mut self, value: u32) { self.0.push(format!("value {}", value)) }
+    }
+
+    let num = int::<char, DefaultError<_>>(10).map(|s| s.parse::<u32>().unwrap());
+    // The first branch emits a value before failing on `3`, which the sink must not see
+    let item = num.clone().emitted().padded_by(just('!'))
+        .or(just('-').padding_for(num.clone().emitted()).node(K::Neg))
+        .or(num.emitted());
+    let list = just('[').padding_for(item.separated_by(just(','))).padded_by(just(']')).node(K::List);
+
+    let mut log = Log::default();
+    assert_eq!(list.parse_sax("[1!,-2,3]".chars(), &mut log).ok(), Some(vec![vec![(); 3]]));
+    assert_eq!(log.0, vec![
+        "start List", "[1", "value 1", "!,", "start Neg", "-2", "value 2", "end Neg", ",3", "value 3", "]", "end List",
+    ]);
+
+    // Counts the numbers passed on, and how far the furthest number parsed is ahead of each one passed on
+    struct Lag(Rc<Cell<usize>>, usize, usize);
+
+    impl Sink<K, char> for Lag {
+        type Value = usize;
+
+        fn value(&mut self, start: usize) {
+            self.1 += 1;
+            self.2 = self.2.max(self.0.get() - start);
+        }
+    }
+
+    // A long array that is one of several alternatives is passed on as it is parsed once the cut after `[` is passed,
+    // even though it is parsed again whenever it runs past the inputs pulled so far
+    let furthest = Rc::new(Cell::new(0));
+    let num = int::<char, DefaultError<_>>(10).map_with_span({
+        let furthest = furthest.clone();
+        move |_, span: Option<std::ops::Range<usize>>| {
+            let start = span.unwrap().start;
+            furthest.set(furthest.get().max(start));
+            start
+        }
+    });
+    let array = just('[').cut().padding_for(num.emitted().separated_by(just(','))).padded_by(just(']')).node(K::List);
+    let value = array.or(just('-').to(Vec::new()));
+
+    let mut lag = Lag(furthest, 0, 0);
+    let src = format!("[{}0]", "12,".repeat(10_000));
+    assert_eq!(value.parse_sax(src.chars(), &mut lag).ok().map(|items| items[0].len()), Some(10_001));
+    assert_eq!((lag.1, lag.2), (10_001, 0));
+
+    // Counts how many more inputs have been pulled from the source than passed on at any point
+    struct Backlog(Rc<Cell<usize>>, usize, usize);
+
+    impl Sink<K, char> for Backlog {
+        type Value = ();
+
+        fn tokens(&mut self, tokens: &[char]) {
+            self.1 += tokens.len();
+            self.2 = self.2.max(self.0.get() - self.1);
+        }
+    }
+
+    // Records are dropped once they have been passed on, so the source is never held all at once
+    let pulled = Rc::new(Cell::new(0));
+    let src = "12,".chars().cycle().take(300_000).inspect({
+        let pulled = pulled.clone();
+        move |_| pulled.set(pulled.get() + 1)
+    });
+    let record = int::<char, DefaultError<_>>(10).emitted().padded_by(just(','));
+
+    let mut backlog = Backlog(pulled, 0, 0);
+    assert_eq!(record.parse_sax(src, &mut backlog).ok().map(|records| records.len()), Some(100_000));
+    assert_eq!(backlog.1, 300_000);
+    assert!(backlog.2 <= 512);
+}
+
+#[cfg(feature = "macros")]
+#[test]
+fn dsl() {
+    fn parser() -> Parser<impl Pattern<DefaultError<char>, Input=char, Output=i64>, DefaultError<char>> {
+        parsers! {
+            number: i64 = { { parze::text::int(10) }~ => { |s: String| s.parse().unwrap() } }
+            atom: i64 = { number | keywords~ | '('~ -& sum &- ')'~ | '-'~ -& atom => { |x: i64| -x } }
+            product: i64 = { atom & ('*'~ -& atom)* => { |(a, bs): (i64, Vec<i64>)| bs.into_iter().fold(a, |a, b| a * b) } }
+            sum: i64 = { ~product & ('+'~ -& product)* => { |(a, bs): (i64, Vec<i64>)| bs.into_iter().fold(a, |a, b| a + b) } }
+            list: Vec<i64> = { '['~ -& (sum ... ','~) &- ']' }
+            keywords: i64 = { "let" -> 1 | "in" % '!'? -> 2 }
+        }
+
+        assert_eq!(list.parse("[1, 2 * 3]".chars()), Ok(vec![1, 6]));
+        assert_eq!(keywords.parse("in!".chars()), Ok(2));
+        assert_eq!(sum.parse("let + in!".chars()), Ok(3));
+
+        sum
+    }
+
+    let p = parser();
+
+    assert_eq!(p.parse(" 1 + 2 * (3 + -4) ".chars()), Ok(-1));
+    assert_eq!(p.parse("2*3*4".chars()), Ok(24));
+    assert!(p.parse("+".chars()).is_err());
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn derive() {
+    #[derive(Parse, Debug, PartialEq)]
+    enum Value {
+        #[parze(keyword = "null")]
+        Null,
+        Num(i64),
+        #[parze(just = "[", after = "]")]
+        List(#[parze(sep = ",")] Vec<Value>),
+        #[parze(just = "-")]
+        Neg(Box<Value>),
+    }
+
+    #[derive(Parse, Debug, PartialEq)]
+    #[parze(keyword = "let")]
+    struct Let {
+        name: String,
+        #[parze(just = ":")]
+        ty: Option<String>,
+        #[parze(just = "=", after = ";")]
+        value: Value,
+    }
+
+    let p = Value::parser::<DefaultError<char>>();
+
+    assert_eq!(p.parse(" [1, null, [-2], []] ".chars()), Ok(Value::List(vec![
+        Value::Num(1),
+        Value::Null,
+        Value::List(vec![Value::Num(-2)]),
+        Value::List(vec![]),
+    ])));
+    assert_eq!(p.parse("- null".chars()), Ok(Value::Neg(Box::new(Value::Null))));
+    assert!(p.parse("nullx".chars()).is_err());
+
+    let p = Let::parser::<SimpleError<char>>();
+
+    assert_eq!(p.parse("let x = 5;".chars()).ok(), Some(Let { name: "x".to_string(), ty: None, value: Value::Num(5) }));
+    assert_eq!(p.parse("let x: int = []; ".chars()).ok(), Some(Let { name: "x".to_string(), ty: Some("int".to_string()), value: Value::List(vec![]) }));
+    assert!(p.parse("let x = 5".chars()).is_err());
+
+    // A recursive type's parser is built once, however deeply it nests
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static BUILT: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug, PartialEq)]
+    struct Nest(Option<Box<Nest>>);
+
+    impl Parse for Nest {
+        fn parser<E: parze::error::Error<char> + 'static>() -> Parser<impl Pattern<E, Input=char, Output=Self> + 'static, E> {
+            BUILT.fetch_add(1, Ordering::SeqCst);
+            just('(').padding_for(parze::parse::lazy::<Nest, E>().or_not()).padded_by(just(')')).map(|n| Nest(n.map(Box::new)))
+        }
+    }
+
+    let p = parze::parse::lazy::<Nest, DefaultError<char>>();
+
+    assert_eq!(p.parse("(((())))".chars()).map(|_| ()), Ok(()));
+    assert_eq!(p.parse("(())".chars()), Ok(Nest(Some(Box::new(Nest(None))))));
+    assert_eq!(BUILT.load(Ordering::SeqCst), 1);
+}